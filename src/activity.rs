@@ -0,0 +1,103 @@
+use crate::config::{Key, KeyPress};
+
+/// How many presses are remembered. Enough for a few seconds of steady
+/// movement, whatever else is typed in between.
+pub const CAPACITY: usize = 64;
+
+/// The most recent key presses, oldest overwritten first. Recording one is a
+/// single write, so the keyboard hook can afford it for every press. Times are
+/// system tick counts, in milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct RecentPresses {
+  presses: [(KeyPress, u32); CAPACITY],
+  /// Where the next press goes
+  next: usize,
+  len: usize,
+}
+
+impl RecentPresses {
+  pub const fn new() -> Self {
+    Self {
+      presses: [(
+        KeyPress {
+          vk: 0,
+          scan: 0,
+        },
+        0,
+      ); CAPACITY],
+      next: 0,
+      len: 0,
+    }
+  }
+
+  pub fn record(&mut self, key: KeyPress, time: u32) {
+    self.presses[self.next] = (key, time);
+    self.next = (self.next + 1) % CAPACITY;
+    self.len = (self.len + 1).min(CAPACITY);
+  }
+
+  /// Presses from newest to oldest.
+  fn iter(&self) -> impl Iterator<Item = (KeyPress, u32)> + '_ {
+    (1..=self.len).map(|back| self.presses[(self.next + CAPACITY - back) % CAPACITY])
+  }
+
+  /// How many presses of any of `keys` there were in the `window_ms` up to
+  /// `now`.
+  pub fn count(&self, keys: &[Key], now: u32, window_ms: u32) -> usize {
+    self
+      .iter()
+      .take_while(|&(_, time)| now.wrapping_sub(time) <= window_ms)
+      .filter(|&(press, _)| keys.iter().any(|key| key.matches(press)))
+      .count()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const W: KeyPress = KeyPress {
+    vk: 0x57,
+    scan: 0x11,
+  };
+  const T: KeyPress = KeyPress {
+    vk: 0x54,
+    scan: 0x14,
+  };
+
+  #[test]
+  fn only_recent_presses_of_the_keys_count() {
+    let mut presses = RecentPresses::new();
+    for (key, time) in [(W, 1000), (T, 1500), (W, 2000), (W, 2500)] {
+      presses.record(key, time);
+    }
+
+    let keys = [Key::vk(W.vk)];
+    assert_eq!(presses.count(&keys, 3000, 1000), 2);
+    assert_eq!(presses.count(&keys, 3000, 2000), 3);
+    assert_eq!(presses.count(&keys, 9000, 1000), 0);
+    assert_eq!(presses.count(&[Key::Scan(T.scan)], 3000, 2000), 1);
+    assert_eq!(presses.count(&[], 3000, 2000), 0);
+  }
+
+  #[test]
+  fn old_presses_are_overwritten() {
+    let mut presses = RecentPresses::new();
+    for time in 0..CAPACITY as u32 + 10 {
+      presses.record(if time < 10 { T } else { W }, time);
+    }
+
+    assert_eq!(presses.count(&[Key::vk(W.vk)], 100, 1000), CAPACITY);
+    assert_eq!(presses.count(&[Key::vk(T.vk)], 100, 1000), 0);
+  }
+
+  #[test]
+  fn timestamps_may_wrap() {
+    let mut presses = RecentPresses::new();
+    presses.record(W, u32::MAX - 100);
+    presses.record(W, 50);
+
+    assert_eq!(presses.count(&[Key::vk(W.vk)], 100, 500), 2);
+    assert_eq!(presses.count(&[Key::vk(W.vk)], 100, 100), 1);
+  }
+}
@@ -1,219 +1,955 @@
 use notify::{Event, EventKind, RecursiveMode, Watcher};
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::{
-  collections::HashMap,
+  collections::HashSet,
   fmt, fs,
+  hash::{Hash, Hasher},
   path::PathBuf,
-  sync::{Arc, RwLock, mpsc},
+  sync::{Arc, mpsc},
   thread,
   time::Duration,
 };
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-  VK_0, VK_1, VK_2, VK_3, VK_4, VK_5, VK_6, VK_7, VK_8, VK_9, VK_A, VK_B, VK_BACK, VK_C,
-  VK_CAPITAL, VK_D, VK_DELETE, VK_DOWN, VK_E, VK_END, VK_ESCAPE, VK_F, VK_F1, VK_F2, VK_F3, VK_F4,
-  VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_F10, VK_F11, VK_F12, VK_F13, VK_F14, VK_F15, VK_F16,
-  VK_F17, VK_F18, VK_F19, VK_F20, VK_F21, VK_F22, VK_F23, VK_F24, VK_G, VK_H, VK_HOME, VK_I,
-  VK_INSERT, VK_J, VK_K, VK_L, VK_LEFT, VK_LWIN, VK_M, VK_N, VK_NEXT, VK_NUMLOCK, VK_O, VK_OEM_1,
-  VK_OEM_2, VK_OEM_3, VK_OEM_4, VK_OEM_5, VK_OEM_6, VK_OEM_7, VK_OEM_COMMA, VK_OEM_MINUS,
-  VK_OEM_PERIOD, VK_OEM_PLUS, VK_P, VK_PAUSE, VK_PRIOR, VK_Q, VK_R, VK_RETURN, VK_RIGHT, VK_RWIN,
-  VK_S, VK_SCROLL, VK_SNAPSHOT, VK_SPACE, VK_T, VK_TAB, VK_U, VK_UP, VK_V, VK_W, VK_X, VK_Y, VK_Z,
+  VK_0, VK_1, VK_2, VK_3, VK_4, VK_5, VK_6, VK_7, VK_8, VK_9, VK_A, VK_ACCEPT, VK_ADD, VK_APPS,
+  VK_B, VK_BACK, VK_BROWSER_BACK, VK_BROWSER_FAVORITES, VK_BROWSER_FORWARD, VK_BROWSER_HOME,
+  VK_BROWSER_REFRESH, VK_BROWSER_SEARCH, VK_BROWSER_STOP, VK_C, VK_CAPITAL, VK_CLEAR, VK_CONVERT,
+  VK_D, VK_DECIMAL, VK_DELETE, VK_DIVIDE, VK_DOWN, VK_E, VK_END, VK_ESCAPE, VK_F, VK_F1, VK_F2,
+  VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_F10, VK_F11, VK_F12, VK_F13, VK_F14, VK_F15,
+  VK_F16, VK_F17, VK_F18, VK_F19, VK_F20, VK_F21, VK_F22, VK_F23, VK_F24, VK_FINAL, VK_G, VK_H,
+  VK_HANGUL, VK_HANJA, VK_HOME, VK_I, VK_IME_OFF, VK_IME_ON, VK_INSERT, VK_J, VK_JUNJA, VK_K,
+  VK_KANA, VK_KANJI, VK_L, VK_LAUNCH_APP1, VK_LAUNCH_APP2, VK_LAUNCH_MAIL, VK_LAUNCH_MEDIA_SELECT,
+  VK_LCONTROL, VK_LEFT, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_M, VK_MEDIA_NEXT_TRACK,
+  VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK, VK_MEDIA_STOP, VK_MODECHANGE, VK_MULTIPLY, VK_N,
+  VK_NEXT, VK_NONCONVERT, VK_NUMLOCK, VK_NUMPAD0, VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4,
+  VK_NUMPAD5, VK_NUMPAD6, VK_NUMPAD7, VK_NUMPAD8, VK_NUMPAD9, VK_O, VK_OEM_1, VK_OEM_2, VK_OEM_3,
+  VK_OEM_4, VK_OEM_5, VK_OEM_6, VK_OEM_7, VK_OEM_COMMA, VK_OEM_MINUS, VK_OEM_PERIOD, VK_OEM_PLUS,
+  VK_P, VK_PAUSE, VK_PRIOR, VK_PROCESSKEY, VK_Q, VK_R, VK_RCONTROL, VK_RETURN, VK_RIGHT, VK_RMENU,
+  VK_RSHIFT, VK_RWIN, VK_S, VK_SCROLL, VK_SEPARATOR, VK_SLEEP, VK_SNAPSHOT, VK_SPACE, VK_SUBTRACT,
+  VK_T, VK_TAB, VK_U, VK_UP, VK_V, VK_VOLUME_DOWN, VK_VOLUME_MUTE, VK_VOLUME_UP, VK_W, VK_X, VK_Y,
+  VK_Z,
 };
 
+use crate::{
+  modifiers::Modifiers,
+  rules::{ActiveConfig, CompiledConfig},
+};
+
+/// Which side(s) of a modifier a key combination requires to be held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ModifierSide {
+  /// Neither side may be held
+  #[default]
+  Released,
+  /// At least one side must be held
+  Either,
+  /// Only the left side may be held
+  Left,
+  /// Only the right side may be held
+  Right,
+  /// Both sides must be held
+  Both,
+}
+
+impl ModifierSide {
+  /// Combines two requirements for the same modifier, e.g. `lctrl+rctrl`.
+  fn merge(self, other: ModifierSide) -> ModifierSide {
+    use ModifierSide::*;
+
+    match (self, other) {
+      (Released, side) | (side, Released) => side,
+      (Either, side) | (side, Either) => side,
+      (Left, Left) => Left,
+      (Right, Right) => Right,
+      _ => Both,
+    }
+  }
+
+  fn matches(self, left: bool, right: bool) -> bool {
+    match self {
+      ModifierSide::Released => !left && !right,
+      ModifierSide::Either => left || right,
+      ModifierSide::Left => left && !right,
+      ModifierSide::Right => !left && right,
+      ModifierSide::Both => left && right,
+    }
+  }
+
+  /// Like `matches`, but ignores any keys the requirement doesn't mention.
+  fn is_satisfied_by(self, left: bool, right: bool) -> bool {
+    match self {
+      ModifierSide::Released => true,
+      ModifierSide::Either => left || right,
+      ModifierSide::Left => left,
+      ModifierSide::Right => right,
+      ModifierSide::Both => left && right,
+    }
+  }
+}
+
+/// Set in `KeyPress::scan` for extended keys, whose scan code is sent with an
+/// E0 prefix.
+pub const EXTENDED_SCAN: u16 = 0x100;
+
+/// A physical key as reported by the keyboard hook.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+  pub vk: u16,
+  /// Scan code, with `EXTENDED_SCAN` set for extended keys
+  pub scan: u16,
+}
+
+impl KeyPress {
+  /// Returns true for extended keys, such as numpad Enter or the navigation
+  /// cluster's Home, which share their virtual-key code with another key.
+  pub fn is_extended(self) -> bool {
+    self.scan & EXTENDED_SCAN != 0
+  }
+}
+
+/// The key a stroke refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+  /// Virtual-key code, from a key name or `vk:`. Keys that share a code are
+  /// told apart by requiring the extended flag to be set or clear.
+  Vk { code: u16, extended: Option<bool> },
+  /// Scan code from `sc:`, with `EXTENDED_SCAN` set for extended keys
+  Scan(u16),
+}
+
+impl Key {
+  /// Matches the virtual-key code whether or not the key is extended.
+  pub const fn vk(code: u16) -> Self {
+    Key::Vk {
+      code,
+      extended: None,
+    }
+  }
+
+  pub fn matches(self, press: KeyPress) -> bool {
+    match self {
+      Key::Vk {
+        code,
+        extended,
+      } => code == press.vk && extended.is_none_or(|extended| extended == press.is_extended()),
+      Key::Scan(scan) => scan == press.scan,
+    }
+  }
+
+  /// Whether this is a modifier key, such as `lctrl` or `sc:0x2a`.
+  fn is_modifier(self) -> bool {
+    match self {
+      Key::Vk {
+        code, ..
+      } => Modifiers::from_vk(code).is_some(),
+      Key::Scan(_) => ModifierGroup::of_key(self).is_some(),
+    }
+  }
+
+  /// Parses a single key, such as `w`, `lshift` or `sc:0x11`. Side-specific
+  /// modifiers are keys here, as they are in the hook.
+  pub fn from_string(s: &str) -> Result<Self, String> {
+    let name = s.trim().to_lowercase();
+    if let Some(raw) = Key::parse_raw(&name) {
+      return raw;
+    }
+    if let Some(&(.., Some(code))) = MODIFIERS.iter().find(|(n, ..)| *n == name) {
+      return Ok(Key::vk(code));
+    }
+    Key::from_name(&name)
+  }
+
+  fn from_name(name: &str) -> Result<Self, String> {
+    if let Some(&(_, code, extended)) = EXTENDED_KEY_NAMES.iter().find(|(n, ..)| *n == name) {
+      return Ok(Key::Vk {
+        code,
+        extended: Some(extended),
+      });
+    }
+
+    key_code(name)
+      .map(Key::vk)
+      .ok_or_else(|| format!("Unknown key: {}", name))
+  }
+
+  /// Parses a raw key code such as `vk:0x5b`, `sc:0x15b` or `sc:e05b`, or
+  /// returns `None` if `name` isn't one. Codes are decimal unless prefixed
+  /// with `0x`; the four-digit `e0xx` form of a scan code is always hex.
+  fn parse_raw(name: &str) -> Option<Result<Self, String>> {
+    let parse_code = |code: &str| {
+      match code.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => code.parse().ok(),
+      }
+    };
+
+    if let Some(code) = name.strip_prefix("vk:") {
+      let vk = parse_code(code).filter(|&vk| vk > 0 && vk < 0xFF);
+      Some(
+        vk.map(Key::vk)
+          .ok_or_else(|| format!("Invalid virtual-key code: {}", code)),
+      )
+    } else if let Some(code) = name.strip_prefix("sc:") {
+      let scan = match code.strip_prefix("e0") {
+        Some(low) if low.len() == 2 => {
+          u16::from_str_radix(low, 16)
+            .ok()
+            .map(|scan| scan | EXTENDED_SCAN)
+        },
+        _ => parse_code(code),
+      };
+      let scan = scan.filter(|&scan| scan & 0xFF != 0 && scan <= (EXTENDED_SCAN | 0xFF));
+      Some(
+        scan
+          .map(Key::Scan)
+          .ok_or_else(|| format!("Invalid scan code: {}", code)),
+      )
+    } else {
+      None
+    }
+  }
+}
+
+/// A single key press with its modifiers, e.g. `ctrl+k`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyStroke {
+  /// Key to match, or `None` to match any key
+  pub key: Option<Key>,
+  pub shift: ModifierSide,
+  pub ctrl: ModifierSide,
+  pub alt: ModifierSide,
+  pub win: ModifierSide,
+  /// Wildcard stroke (`*` or `any`): the listed modifiers must be held, but
+  /// any others may be held as well
+  pub any_modifiers: bool,
+}
+
+/// A rule: one stroke, or a chord of several strokes separated by spaces,
+/// such as `ctrl+k ctrl+c`.
 #[derive(Debug, Clone)]
 pub struct KeyCombo {
-  pub key: u16,
-  pub shift: bool,
-  pub ctrl: bool,
-  pub alt: bool,
-  pub win: bool,
+  /// Strokes in the order they have to be pressed; never empty
+  pub strokes: Vec<KeyStroke>,
+  /// Tap-only rule (`tap:` prefix): the key and its combinations pass through,
+  /// but releasing it without pressing another key in between is masked so it
+  /// doesn't open the Start menu or activate the menu bar
+  pub tap: bool,
   pub string_repr: String,
 }
 
-fn create_key_map() -> HashMap<String, u16> {
-  let mut map = HashMap::new();
+/// Longest chord a rule may describe.
+pub const MAX_CHORD_STROKES: usize = 4;
 
+/// Key names accepted in key combinations. The first name listed for a code
+/// is its canonical name, used when writing combinations back out.
+const KEY_NAMES: &[(&str, u16)] = &[
   // Letters
-  map.insert("a".to_string(), VK_A.0);
-  map.insert("b".to_string(), VK_B.0);
-  map.insert("c".to_string(), VK_C.0);
-  map.insert("d".to_string(), VK_D.0);
-  map.insert("e".to_string(), VK_E.0);
-  map.insert("f".to_string(), VK_F.0);
-  map.insert("g".to_string(), VK_G.0);
-  map.insert("h".to_string(), VK_H.0);
-  map.insert("i".to_string(), VK_I.0);
-  map.insert("j".to_string(), VK_J.0);
-  map.insert("k".to_string(), VK_K.0);
-  map.insert("l".to_string(), VK_L.0);
-  map.insert("m".to_string(), VK_M.0);
-  map.insert("n".to_string(), VK_N.0);
-  map.insert("o".to_string(), VK_O.0);
-  map.insert("p".to_string(), VK_P.0);
-  map.insert("q".to_string(), VK_Q.0);
-  map.insert("r".to_string(), VK_R.0);
-  map.insert("s".to_string(), VK_S.0);
-  map.insert("t".to_string(), VK_T.0);
-  map.insert("u".to_string(), VK_U.0);
-  map.insert("v".to_string(), VK_V.0);
-  map.insert("w".to_string(), VK_W.0);
-  map.insert("x".to_string(), VK_X.0);
-  map.insert("y".to_string(), VK_Y.0);
-  map.insert("z".to_string(), VK_Z.0);
-
+  ("a", VK_A.0),
+  ("b", VK_B.0),
+  ("c", VK_C.0),
+  ("d", VK_D.0),
+  ("e", VK_E.0),
+  ("f", VK_F.0),
+  ("g", VK_G.0),
+  ("h", VK_H.0),
+  ("i", VK_I.0),
+  ("j", VK_J.0),
+  ("k", VK_K.0),
+  ("l", VK_L.0),
+  ("m", VK_M.0),
+  ("n", VK_N.0),
+  ("o", VK_O.0),
+  ("p", VK_P.0),
+  ("q", VK_Q.0),
+  ("r", VK_R.0),
+  ("s", VK_S.0),
+  ("t", VK_T.0),
+  ("u", VK_U.0),
+  ("v", VK_V.0),
+  ("w", VK_W.0),
+  ("x", VK_X.0),
+  ("y", VK_Y.0),
+  ("z", VK_Z.0),
   // Numbers
-  map.insert("0".to_string(), VK_0.0);
-  map.insert("1".to_string(), VK_1.0);
-  map.insert("2".to_string(), VK_2.0);
-  map.insert("3".to_string(), VK_3.0);
-  map.insert("4".to_string(), VK_4.0);
-  map.insert("5".to_string(), VK_5.0);
-  map.insert("6".to_string(), VK_6.0);
-  map.insert("7".to_string(), VK_7.0);
-  map.insert("8".to_string(), VK_8.0);
-  map.insert("9".to_string(), VK_9.0);
-
+  ("0", VK_0.0),
+  ("1", VK_1.0),
+  ("2", VK_2.0),
+  ("3", VK_3.0),
+  ("4", VK_4.0),
+  ("5", VK_5.0),
+  ("6", VK_6.0),
+  ("7", VK_7.0),
+  ("8", VK_8.0),
+  ("9", VK_9.0),
   // Function keys
-  map.insert("f1".to_string(), VK_F1.0);
-  map.insert("f2".to_string(), VK_F2.0);
-  map.insert("f3".to_string(), VK_F3.0);
-  map.insert("f4".to_string(), VK_F4.0);
-  map.insert("f5".to_string(), VK_F5.0);
-  map.insert("f6".to_string(), VK_F6.0);
-  map.insert("f7".to_string(), VK_F7.0);
-  map.insert("f8".to_string(), VK_F8.0);
-  map.insert("f9".to_string(), VK_F9.0);
-  map.insert("f10".to_string(), VK_F10.0);
-  map.insert("f11".to_string(), VK_F11.0);
-  map.insert("f12".to_string(), VK_F12.0);
-  map.insert("f13".to_string(), VK_F13.0);
-  map.insert("f14".to_string(), VK_F14.0);
-  map.insert("f15".to_string(), VK_F15.0);
-  map.insert("f16".to_string(), VK_F16.0);
-  map.insert("f17".to_string(), VK_F17.0);
-  map.insert("f18".to_string(), VK_F18.0);
-  map.insert("f19".to_string(), VK_F19.0);
-  map.insert("f20".to_string(), VK_F20.0);
-  map.insert("f21".to_string(), VK_F21.0);
-  map.insert("f22".to_string(), VK_F22.0);
-  map.insert("f23".to_string(), VK_F23.0);
-  map.insert("f24".to_string(), VK_F24.0);
-
+  ("f1", VK_F1.0),
+  ("f2", VK_F2.0),
+  ("f3", VK_F3.0),
+  ("f4", VK_F4.0),
+  ("f5", VK_F5.0),
+  ("f6", VK_F6.0),
+  ("f7", VK_F7.0),
+  ("f8", VK_F8.0),
+  ("f9", VK_F9.0),
+  ("f10", VK_F10.0),
+  ("f11", VK_F11.0),
+  ("f12", VK_F12.0),
+  ("f13", VK_F13.0),
+  ("f14", VK_F14.0),
+  ("f15", VK_F15.0),
+  ("f16", VK_F16.0),
+  ("f17", VK_F17.0),
+  ("f18", VK_F18.0),
+  ("f19", VK_F19.0),
+  ("f20", VK_F20.0),
+  ("f21", VK_F21.0),
+  ("f22", VK_F22.0),
+  ("f23", VK_F23.0),
+  ("f24", VK_F24.0),
   // Special keys
-  map.insert("space".to_string(), VK_SPACE.0);
-  map.insert("enter".to_string(), VK_RETURN.0);
-  map.insert("return".to_string(), VK_RETURN.0);
-  map.insert("tab".to_string(), VK_TAB.0);
-  map.insert("escape".to_string(), VK_ESCAPE.0);
-  map.insert("esc".to_string(), VK_ESCAPE.0);
-  map.insert("backspace".to_string(), VK_BACK.0);
-  map.insert("delete".to_string(), VK_DELETE.0);
-  map.insert("del".to_string(), VK_DELETE.0);
-  map.insert("insert".to_string(), VK_INSERT.0);
-  map.insert("ins".to_string(), VK_INSERT.0);
-  map.insert("home".to_string(), VK_HOME.0);
-  map.insert("end".to_string(), VK_END.0);
-  map.insert("pageup".to_string(), VK_PRIOR.0);
-  map.insert("pagedown".to_string(), VK_NEXT.0);
-  map.insert("up".to_string(), VK_UP.0);
-  map.insert("down".to_string(), VK_DOWN.0);
-  map.insert("left".to_string(), VK_LEFT.0);
-  map.insert("right".to_string(), VK_RIGHT.0);
-  map.insert("printscreen".to_string(), VK_SNAPSHOT.0);
-  map.insert("prtsc".to_string(), VK_SNAPSHOT.0);
-  map.insert("pause".to_string(), VK_PAUSE.0);
-  map.insert("capslock".to_string(), VK_CAPITAL.0);
-  map.insert("numlock".to_string(), VK_NUMLOCK.0);
-  map.insert("scrolllock".to_string(), VK_SCROLL.0);
-
+  ("space", VK_SPACE.0),
+  ("enter", VK_RETURN.0),
+  ("return", VK_RETURN.0),
+  ("tab", VK_TAB.0),
+  ("escape", VK_ESCAPE.0),
+  ("esc", VK_ESCAPE.0),
+  ("backspace", VK_BACK.0),
+  ("delete", VK_DELETE.0),
+  ("del", VK_DELETE.0),
+  ("insert", VK_INSERT.0),
+  ("ins", VK_INSERT.0),
+  ("home", VK_HOME.0),
+  ("end", VK_END.0),
+  ("pageup", VK_PRIOR.0),
+  ("pagedown", VK_NEXT.0),
+  ("up", VK_UP.0),
+  ("down", VK_DOWN.0),
+  ("left", VK_LEFT.0),
+  ("right", VK_RIGHT.0),
+  ("printscreen", VK_SNAPSHOT.0),
+  ("prtsc", VK_SNAPSHOT.0),
+  ("pause", VK_PAUSE.0),
+  ("capslock", VK_CAPITAL.0),
+  ("numlock", VK_NUMLOCK.0),
+  ("scrolllock", VK_SCROLL.0),
   // Punctuation
-  map.insert("semicolon".to_string(), VK_OEM_1.0);
-  map.insert("equals".to_string(), VK_OEM_PLUS.0);
-  map.insert("comma".to_string(), VK_OEM_COMMA.0);
-  map.insert("minus".to_string(), VK_OEM_MINUS.0);
-  map.insert("period".to_string(), VK_OEM_PERIOD.0);
-  map.insert("slash".to_string(), VK_OEM_2.0);
-  map.insert("grave".to_string(), VK_OEM_3.0);
-  map.insert("leftbracket".to_string(), VK_OEM_4.0);
-  map.insert("backslash".to_string(), VK_OEM_5.0);
-  map.insert("rightbracket".to_string(), VK_OEM_6.0);
-  map.insert("quote".to_string(), VK_OEM_7.0);
-
+  ("semicolon", VK_OEM_1.0),
+  ("equals", VK_OEM_PLUS.0),
+  ("comma", VK_OEM_COMMA.0),
+  ("minus", VK_OEM_MINUS.0),
+  ("period", VK_OEM_PERIOD.0),
+  ("slash", VK_OEM_2.0),
+  ("grave", VK_OEM_3.0),
+  ("leftbracket", VK_OEM_4.0),
+  ("backslash", VK_OEM_5.0),
+  ("rightbracket", VK_OEM_6.0),
+  ("quote", VK_OEM_7.0),
   // Windows keys
-  map.insert("super".to_string(), VK_LWIN.0);
-  map.insert("lwin".to_string(), VK_LWIN.0);
-  map.insert("rwin".to_string(), VK_RWIN.0);
+  ("lwin", VK_LWIN.0),
+  ("rwin", VK_RWIN.0),
+  ("super", VK_LWIN.0),
+  // Numeric keypad
+  ("numpad0", VK_NUMPAD0.0),
+  ("numpad1", VK_NUMPAD1.0),
+  ("numpad2", VK_NUMPAD2.0),
+  ("numpad3", VK_NUMPAD3.0),
+  ("numpad4", VK_NUMPAD4.0),
+  ("numpad5", VK_NUMPAD5.0),
+  ("numpad6", VK_NUMPAD6.0),
+  ("numpad7", VK_NUMPAD7.0),
+  ("numpad8", VK_NUMPAD8.0),
+  ("numpad9", VK_NUMPAD9.0),
+  ("num0", VK_NUMPAD0.0),
+  ("num1", VK_NUMPAD1.0),
+  ("num2", VK_NUMPAD2.0),
+  ("num3", VK_NUMPAD3.0),
+  ("num4", VK_NUMPAD4.0),
+  ("num5", VK_NUMPAD5.0),
+  ("num6", VK_NUMPAD6.0),
+  ("num7", VK_NUMPAD7.0),
+  ("num8", VK_NUMPAD8.0),
+  ("num9", VK_NUMPAD9.0),
+  ("numpadmultiply", VK_MULTIPLY.0),
+  ("multiply", VK_MULTIPLY.0),
+  ("numpadadd", VK_ADD.0),
+  ("add", VK_ADD.0),
+  ("numpadseparator", VK_SEPARATOR.0),
+  ("separator", VK_SEPARATOR.0),
+  ("numpadsubtract", VK_SUBTRACT.0),
+  ("subtract", VK_SUBTRACT.0),
+  ("numpaddecimal", VK_DECIMAL.0),
+  ("decimal", VK_DECIMAL.0),
+  ("numpaddivide", VK_DIVIDE.0),
+  ("divide", VK_DIVIDE.0),
+  ("clear", VK_CLEAR.0),
+  // Media keys
+  ("playpause", VK_MEDIA_PLAY_PAUSE.0),
+  ("mediaplaypause", VK_MEDIA_PLAY_PAUSE.0),
+  ("mediastop", VK_MEDIA_STOP.0),
+  ("nexttrack", VK_MEDIA_NEXT_TRACK.0),
+  ("medianext", VK_MEDIA_NEXT_TRACK.0),
+  ("prevtrack", VK_MEDIA_PREV_TRACK.0),
+  ("previoustrack", VK_MEDIA_PREV_TRACK.0),
+  ("mediaprev", VK_MEDIA_PREV_TRACK.0),
+  ("volumeup", VK_VOLUME_UP.0),
+  ("volup", VK_VOLUME_UP.0),
+  ("volumedown", VK_VOLUME_DOWN.0),
+  ("voldown", VK_VOLUME_DOWN.0),
+  ("volumemute", VK_VOLUME_MUTE.0),
+  ("mute", VK_VOLUME_MUTE.0),
+  // Browser keys
+  ("browserback", VK_BROWSER_BACK.0),
+  ("browserforward", VK_BROWSER_FORWARD.0),
+  ("browserrefresh", VK_BROWSER_REFRESH.0),
+  ("browserstop", VK_BROWSER_STOP.0),
+  ("browsersearch", VK_BROWSER_SEARCH.0),
+  ("browserfavorites", VK_BROWSER_FAVORITES.0),
+  ("browserhome", VK_BROWSER_HOME.0),
+  // Launcher keys
+  ("launchmail", VK_LAUNCH_MAIL.0),
+  ("mail", VK_LAUNCH_MAIL.0),
+  ("launchmedia", VK_LAUNCH_MEDIA_SELECT.0),
+  ("mediaselect", VK_LAUNCH_MEDIA_SELECT.0),
+  ("launchapp1", VK_LAUNCH_APP1.0),
+  ("launchapp2", VK_LAUNCH_APP2.0),
+  ("sleep", VK_SLEEP.0),
+  // Application (context menu) key
+  ("apps", VK_APPS.0),
+  ("menu", VK_APPS.0),
+  ("contextmenu", VK_APPS.0),
+  ("application", VK_APPS.0),
+  // IME keys. Kana/Hangul and Hanja/Kanji share a code.
+  ("kana", VK_KANA.0),
+  ("hangul", VK_HANGUL.0),
+  ("junja", VK_JUNJA.0),
+  ("final", VK_FINAL.0),
+  ("hanja", VK_HANJA.0),
+  ("kanji", VK_KANJI.0),
+  ("convert", VK_CONVERT.0),
+  ("nonconvert", VK_NONCONVERT.0),
+  ("accept", VK_ACCEPT.0),
+  ("modechange", VK_MODECHANGE.0),
+  ("imeon", VK_IME_ON.0),
+  ("imeoff", VK_IME_OFF.0),
+  ("processkey", VK_PROCESSKEY.0),
+];
 
-  map
+/// Virtual-key code for a key name.
+fn key_code(name: &str) -> Option<u16> {
+  KEY_NAMES
+    .iter()
+    .find(|&&(n, _)| n == name)
+    .map(|&(_, vk)| vk)
 }
 
-impl KeyCombo {
-  pub fn from_string(s: &str) -> Result<Self, String> {
-    let key_map = create_key_map();
+/// Canonical name of a virtual-key code, if it has one.
+fn key_name(vk: u16) -> Option<&'static str> {
+  KEY_NAMES
+    .iter()
+    .find(|&&(_, code)| code == vk)
+    .map(|&(name, _)| name)
+}
+
+/// Names for keys that share a virtual-key code with another key, and are
+/// told apart by the extended flag. The plain names (`enter`, `home`, ...)
+/// match either key.
+const EXTENDED_KEY_NAMES: &[(&str, u16, bool)] = &[
+  ("numpadenter", VK_RETURN.0, true),
+  ("mainenter", VK_RETURN.0, false),
+  ("navinsert", VK_INSERT.0, true),
+  ("navdelete", VK_DELETE.0, true),
+  ("navhome", VK_HOME.0, true),
+  ("navend", VK_END.0, true),
+  ("navpageup", VK_PRIOR.0, true),
+  ("navpagedown", VK_NEXT.0, true),
+  ("navup", VK_UP.0, true),
+  ("navdown", VK_DOWN.0, true),
+  ("navleft", VK_LEFT.0, true),
+  ("navright", VK_RIGHT.0, true),
+  // The numpad sends these with Num Lock off
+  ("numpadinsert", VK_INSERT.0, false),
+  ("numpaddelete", VK_DELETE.0, false),
+  ("numpadhome", VK_HOME.0, false),
+  ("numpadend", VK_END.0, false),
+  ("numpadpageup", VK_PRIOR.0, false),
+  ("numpadpagedown", VK_NEXT.0, false),
+  ("numpadup", VK_UP.0, false),
+  ("numpaddown", VK_DOWN.0, false),
+  ("numpadleft", VK_LEFT.0, false),
+  ("numpadright", VK_RIGHT.0, false),
+];
+
+/// Modifier names accepted in key combinations. Side-specific names also carry
+/// the virtual-key code used when the modifier is the only part of a combination.
+const MODIFIERS: &[(&str, ModifierGroup, ModifierSide, Option<u16>)] = &[
+  ("shift", ModifierGroup::Shift, ModifierSide::Either, None),
+  (
+    "lshift",
+    ModifierGroup::Shift,
+    ModifierSide::Left,
+    Some(VK_LSHIFT.0),
+  ),
+  (
+    "rshift",
+    ModifierGroup::Shift,
+    ModifierSide::Right,
+    Some(VK_RSHIFT.0),
+  ),
+  ("ctrl", ModifierGroup::Ctrl, ModifierSide::Either, None),
+  ("control", ModifierGroup::Ctrl, ModifierSide::Either, None),
+  (
+    "lctrl",
+    ModifierGroup::Ctrl,
+    ModifierSide::Left,
+    Some(VK_LCONTROL.0),
+  ),
+  (
+    "lcontrol",
+    ModifierGroup::Ctrl,
+    ModifierSide::Left,
+    Some(VK_LCONTROL.0),
+  ),
+  (
+    "rctrl",
+    ModifierGroup::Ctrl,
+    ModifierSide::Right,
+    Some(VK_RCONTROL.0),
+  ),
+  (
+    "rcontrol",
+    ModifierGroup::Ctrl,
+    ModifierSide::Right,
+    Some(VK_RCONTROL.0),
+  ),
+  ("alt", ModifierGroup::Alt, ModifierSide::Either, None),
+  (
+    "lalt",
+    ModifierGroup::Alt,
+    ModifierSide::Left,
+    Some(VK_LMENU.0),
+  ),
+  (
+    "ralt",
+    ModifierGroup::Alt,
+    ModifierSide::Right,
+    Some(VK_RMENU.0),
+  ),
+  ("win", ModifierGroup::Win, ModifierSide::Either, None),
+  (
+    "super",
+    ModifierGroup::Win,
+    ModifierSide::Either,
+    Some(VK_LWIN.0),
+  ),
+  (
+    "lwin",
+    ModifierGroup::Win,
+    ModifierSide::Left,
+    Some(VK_LWIN.0),
+  ),
+  (
+    "rwin",
+    ModifierGroup::Win,
+    ModifierSide::Right,
+    Some(VK_RWIN.0),
+  ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModifierGroup {
+  Shift,
+  Ctrl,
+  Alt,
+  Win,
+}
+
+impl ModifierGroup {
+  /// Order in which modifiers are written in the canonical form.
+  const ALL: [ModifierGroup; 4] = [
+    ModifierGroup::Ctrl,
+    ModifierGroup::Alt,
+    ModifierGroup::Shift,
+    ModifierGroup::Win,
+  ];
+
+  /// Canonical names for either side, the left side and the right side.
+  fn names(self) -> [&'static str; 3] {
+    match self {
+      ModifierGroup::Shift => ["shift", "lshift", "rshift"],
+      ModifierGroup::Ctrl => ["ctrl", "lctrl", "rctrl"],
+      ModifierGroup::Alt => ["alt", "lalt", "ralt"],
+      ModifierGroup::Win => ["win", "lwin", "rwin"],
+    }
+  }
+
+  /// Group and side of a side-specific modifier key, given by its
+  /// virtual-key code or its scan code.
+  fn of_key(key: Key) -> Option<(Self, ModifierSide)> {
+    let vk = match key {
+      Key::Vk {
+        code,
+        extended: None,
+      } => code,
+      Key::Vk {
+        ..
+      } => return None,
+      Key::Scan(scan) => {
+        MODIFIER_SCAN_CODES
+          .iter()
+          .find(|&&(s, _)| s == scan)
+          .map(|&(_, vk)| vk)?
+      },
+    };
+    MODIFIERS
+      .iter()
+      .find(|&&(_, _, side, code)| {
+        code == Some(vk) && matches!(side, ModifierSide::Left | ModifierSide::Right)
+      })
+      .map(|&(_, group, side, _)| (group, side))
+  }
+}
+
+/// Scan codes of the side-specific modifier keys, with `EXTENDED_SCAN` set
+/// for the extended ones, and their virtual-key codes.
+const MODIFIER_SCAN_CODES: [(u16, u16); 8] = [
+  (0x2A, VK_LSHIFT.0),
+  (0x36, VK_RSHIFT.0),
+  (0x1D, VK_LCONTROL.0),
+  (0x1D | EXTENDED_SCAN, VK_RCONTROL.0),
+  (0x38, VK_LMENU.0),
+  (0x38 | EXTENDED_SCAN, VK_RMENU.0),
+  (0x5B | EXTENDED_SCAN, VK_LWIN.0),
+  (0x5C | EXTENDED_SCAN, VK_RWIN.0),
+];
+
+impl KeyStroke {
+  fn side(&self, group: ModifierGroup) -> ModifierSide {
+    match group {
+      ModifierGroup::Shift => self.shift,
+      ModifierGroup::Ctrl => self.ctrl,
+      ModifierGroup::Alt => self.alt,
+      ModifierGroup::Win => self.win,
+    }
+  }
+
+  fn from_string(s: &str) -> Result<Self, String> {
     let parts: Vec<String> = s.split('+').map(|p| p.trim().to_lowercase()).collect();
 
     if parts.is_empty() {
       return Err("Empty key combination".to_string());
     }
 
-    let mut shift = false;
-    let mut ctrl = false;
-    let mut alt = false;
-    let mut win = false;
+    let mut shift = ModifierSide::Released;
+    let mut ctrl = ModifierSide::Released;
+    let mut alt = ModifierSide::Released;
+    let mut win = ModifierSide::Released;
+    let mut any_modifiers = false;
+    let mut modifier_key = None;
+    let mut ends_with_modifier_key = false;
     let mut key_name = None;
 
-    for part in &parts {
-      match part.as_str() {
-        "shift" => shift = true,
-        "ctrl" | "control" => ctrl = true,
-        "alt" => alt = true,
-        "lwin" | "super" => {
-          // Special case: if "lwin" is the only part, treat it as the key itself
-          if parts.len() == 1 {
-            key_name = Some("lwin");
-          }
-          win = true;
-        },
-        "rwin" => {
-          // Special case: if "rwin" is the only part, treat it as the key itself
-          if parts.len() == 1 {
-            key_name = Some("rwin");
-          }
-          win = true;
-        },
-        _ => {
-          if key_name.is_some() {
-            return Err(format!(
-              "Multiple keys specified: {} and {}",
-              key_name.as_ref().unwrap(),
-              part
-            ));
-          }
-          key_name = Some(part.as_str());
-        },
+    for (i, part) in parts.iter().enumerate() {
+      if part == "*" || part == "any" {
+        any_modifiers = true;
+        continue;
+      }
+
+      if let Some(&(_, group, side, vk)) = MODIFIERS.iter().find(|(name, ..)| name == part) {
+        // A side-specific modifier in the key position (e.g. 'lwin' or
+        // 'shift+lwin') is also the key itself
+        if vk.is_some() {
+          modifier_key = vk;
+          ends_with_modifier_key = i == parts.len() - 1;
+        }
+
+        let current = match group {
+          ModifierGroup::Shift => &mut shift,
+          ModifierGroup::Ctrl => &mut ctrl,
+          ModifierGroup::Alt => &mut alt,
+          ModifierGroup::Win => &mut win,
+        };
+        *current = current.merge(side);
+        continue;
+      }
+
+      if let Some(existing) = key_name {
+        return Err(format!(
+          "Multiple keys specified: {} and {}",
+          existing, part
+        ));
       }
+      key_name = Some(part.as_str());
     }
 
-    let key_name = key_name.ok_or("No key specified".to_string())?;
-    let key_code = key_map
-      .get(key_name)
-      .ok_or_else(|| format!("Unknown key: {}", key_name))?;
+    let key = match (key_name, modifier_key) {
+      (Some(name), _) => {
+        match Key::parse_raw(name) {
+          Some(raw) => Some(raw?),
+          None => Some(Key::from_name(name)?),
+        }
+      },
+      // Without a wildcard the key may also come first, e.g. 'lwin+shift'
+      (None, Some(code)) if ends_with_modifier_key || !any_modifiers => Some(Key::vk(code)),
+      // A wildcard without a key stands for any key, e.g. 'win+*'
+      (None, _) if any_modifiers => None,
+      (None, _) => return Err("No key specified".to_string()),
+    };
+
+    // A modifier key is held while it's pressed, so a raw code such as
+    // 'vk:0x5b' or 'sc:e05b' means the same as 'lwin'
+    if let Some(key) = key
+      && let Some((group, side)) = ModifierGroup::of_key(key)
+    {
+      let current = match group {
+        ModifierGroup::Shift => &mut shift,
+        ModifierGroup::Ctrl => &mut ctrl,
+        ModifierGroup::Alt => &mut alt,
+        ModifierGroup::Win => &mut win,
+      };
+      *current = current.merge(side);
+    }
 
     Ok(Self {
-      key: *key_code,
+      key,
       shift,
       ctrl,
       alt,
       win,
+      any_modifiers,
+    })
+  }
+
+  /// Returns true if the modifiers held match this stroke's requirements.
+  pub fn matches_modifiers(&self, mods: Modifiers) -> bool {
+    let side_matches = |side: ModifierSide, left: Modifiers, right: Modifiers| {
+      let (left, right) = (mods.contains(left), mods.contains(right));
+      if self.any_modifiers {
+        side.is_satisfied_by(left, right)
+      } else {
+        side.matches(left, right)
+      }
+    };
+
+    side_matches(self.shift, Modifiers::LSHIFT, Modifiers::RSHIFT)
+      && side_matches(self.ctrl, Modifiers::LCTRL, Modifiers::RCTRL)
+      && side_matches(self.alt, Modifiers::LALT, Modifiers::RALT)
+      && side_matches(self.win, Modifiers::LWIN, Modifiers::RWIN)
+  }
+
+  /// Exact strokes beat wildcard strokes for a named key, which beat wildcard
+  /// strokes for any key.
+  fn specificity(&self) -> u8 {
+    match (self.key, self.any_modifiers) {
+      (Some(_), false) => 2,
+      (Some(_), true) => 1,
+      (None, _) => 0,
+    }
+  }
+}
+
+impl fmt::Display for Key {
+  /// Writes the key by its canonical name, or as a raw code if it has none.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (code, name) = match *self {
+      Key::Vk {
+        code,
+        extended: None,
+      } => {
+        let name = key_name(code).or_else(|| {
+          MODIFIERS
+            .iter()
+            .find(|&&(.., vk)| vk == Some(code))
+            .map(|&(name, ..)| name)
+        });
+        (code, name)
+      },
+      Key::Vk {
+        code,
+        extended: Some(extended),
+      } => {
+        let name = EXTENDED_KEY_NAMES
+          .iter()
+          .find(|&&(_, c, e)| c == code && e == extended)
+          .map(|&(name, ..)| name);
+        (code, name)
+      },
+      Key::Scan(scan) => return write!(f, "sc:0x{:X}", scan),
+    };
+    match name {
+      Some(name) => f.write_str(name),
+      None => write!(f, "vk:0x{:02X}", code),
+    }
+  }
+}
+
+impl Serialize for Key {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(&self.to_string())
+  }
+}
+
+impl<'de> Deserialize<'de> for Key {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let s = String::deserialize(deserializer)?;
+    Key::from_string(&s).map_err(serde::de::Error::custom)
+  }
+}
+
+impl fmt::Display for KeyStroke {
+  /// Writes the canonical form: modifiers in a fixed order, then `*`, then
+  /// the key by its canonical name.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut sides = ModifierGroup::ALL.map(|group| (group, self.side(group)));
+
+    let key = self.key.map(|key| {
+      let Some((group, key_side)) = ModifierGroup::of_key(key) else {
+        return key.to_string();
+      };
+      // A side-specific modifier key holds its own side, so only the other
+      // side is written out (e.g. 'lctrl+rctrl')
+      if let Some((_, side)) = sides.iter_mut().find(|(g, _)| *g == group) {
+        *side = match (*side, key_side) {
+          (ModifierSide::Both, ModifierSide::Left) => ModifierSide::Right,
+          (ModifierSide::Both, _) => ModifierSide::Left,
+          _ => ModifierSide::Released,
+        };
+      }
+      match key {
+        Key::Vk {
+          ..
+        } => {
+          let [_, left, right] = group.names();
+          if key_side == ModifierSide::Left {
+            left.to_string()
+          } else {
+            right.to_string()
+          }
+        },
+        // Scan codes are kept, since they stand for the physical key
+        Key::Scan(_) => key.to_string(),
+      }
+    });
+
+    let mut parts = Vec::new();
+    for (group, side) in sides {
+      let [either, left, right] = group.names();
+      match side {
+        ModifierSide::Released => {},
+        ModifierSide::Either => parts.push(either),
+        ModifierSide::Left => parts.push(left),
+        ModifierSide::Right => parts.push(right),
+        ModifierSide::Both => parts.extend([left, right]),
+      }
+    }
+    if self.any_modifiers {
+      parts.push("*");
+    }
+    if let Some(key) = &key {
+      parts.push(key);
+    }
+
+    write!(f, "{}", parts.join("+"))
+  }
+}
+
+impl KeyCombo {
+  pub fn from_string(s: &str) -> Result<Self, String> {
+    let trimmed = s.trim();
+    let (tap, combo) = match trimmed.get(..4) {
+      Some(prefix) if prefix.eq_ignore_ascii_case("tap:") => (true, &trimmed[4..]),
+      _ => (false, trimmed),
+    };
+
+    // Spaces around '+' are optional, so drop them before splitting the
+    // chord into strokes on the remaining whitespace
+    let combo = combo
+      .split('+')
+      .map(str::trim)
+      .collect::<Vec<_>>()
+      .join("+");
+    let strokes = combo
+      .split_whitespace()
+      .map(KeyStroke::from_string)
+      .collect::<Result<Vec<_>, _>>()?;
+
+    if strokes.is_empty() {
+      return Err("Empty key combination".to_string());
+    }
+    if strokes.len() > MAX_CHORD_STROKES {
+      return Err(format!(
+        "Chords can have at most {} strokes",
+        MAX_CHORD_STROKES
+      ));
+    }
+    // Chords are matched against the keys pressed, leaving out modifier keys
+    // so they can be let go and pressed again between strokes
+    if strokes.len() > 1
+      && strokes
+        .iter()
+        .any(|stroke| stroke.key.is_some_and(Key::is_modifier))
+    {
+      return Err("Chords can't have a modifier key as a stroke of its own".to_string());
+    }
+    if tap && (strokes.len() > 1 || strokes[0].key.is_none()) {
+      return Err("Tap-only rules need a single key".to_string());
+    }
+
+    Ok(Self {
+      strokes,
+      tap,
       string_repr: s.to_string(),
     })
   }
 
-  pub fn matches(&self, key: u16, shift: bool, ctrl: bool, alt: bool, win: bool) -> bool {
-    self.key == key
-      && self.shift == shift
-      && self.ctrl == ctrl
-      && self.alt == alt
-      && self.win == win
+  /// How specific this rule is. When a key matches both lists, the more
+  /// specific rule wins: a completed chord beats any single stroke, then exact
+  /// strokes beat wildcard strokes for a named key, which beat wildcard
+  /// strokes for any key.
+  pub fn specificity(&self) -> u8 {
+    let last = &self.strokes[self.strokes.len() - 1];
+    (self.strokes.len() as u8 - 1) * 3 + last.specificity()
+  }
+
+  /// Replaces the string this combo was written as with its canonical form.
+  pub fn normalize(&mut self) {
+    self.string_repr = self.to_string();
+  }
+}
+
+impl fmt::Display for KeyCombo {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.tap {
+      write!(f, "tap:")?;
+    }
+    for (i, stroke) in self.strokes.iter().enumerate() {
+      if i > 0 {
+        write!(f, " ")?;
+      }
+      write!(f, "{}", stroke)?;
+    }
+    Ok(())
+  }
+}
+
+/// Combos are equal when they match the same keys, however they were written.
+impl PartialEq for KeyCombo {
+  fn eq(&self, other: &Self) -> bool {
+    self.strokes == other.strokes && self.tap == other.tap
+  }
+}
+
+impl Eq for KeyCombo {}
+
+impl Hash for KeyCombo {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.strokes.hash(state);
+    self.tap.hash(state);
   }
 }
 
@@ -243,13 +979,23 @@ pub enum DetectMethod {
   NotificationState,
   Fullscreen,
   WindowStyle,
+  /// The foreground executable is installed with a game from a launcher's
+  /// library
+  GameLibrary,
+  /// The cursor is confined to the foreground window or hidden over it
+  InputCapture,
+  /// Movement keys have been pressed steadily in the last few seconds
+  KeyActivity,
 }
 
 impl DetectMethod {
-  pub const ALL: [DetectMethod; 3] = [
+  pub const ALL: [DetectMethod; 6] = [
     DetectMethod::NotificationState,
     DetectMethod::Fullscreen,
     DetectMethod::WindowStyle,
+    DetectMethod::GameLibrary,
+    DetectMethod::InputCapture,
+    DetectMethod::KeyActivity,
   ];
 
   pub fn display_name(&self) -> &'static str {
@@ -257,6 +1003,9 @@ impl DetectMethod {
       DetectMethod::NotificationState => "Notification State",
       DetectMethod::Fullscreen => "Fullscreen",
       DetectMethod::WindowStyle => "Window Style",
+      DetectMethod::GameLibrary => "Game Library",
+      DetectMethod::InputCapture => "Input Capture",
+      DetectMethod::KeyActivity => "Key Activity",
     }
   }
 }
@@ -267,6 +1016,546 @@ impl fmt::Display for DetectMethod {
   }
 }
 
+/// What Windows reports through `SHQueryUserNotificationState`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationState {
+  /// The screen saver is running, the machine is locked or another user is
+  /// active
+  NotPresent,
+  /// A fullscreen application is running or presentation settings are on
+  Busy,
+  /// A fullscreen Direct3D application is running
+  D3dFullscreen,
+  /// Presentation mode is on
+  PresentationMode,
+  /// Nothing is suppressing notifications
+  AcceptsNotifications,
+  /// The first hour after a new user's first sign-in
+  QuietTime,
+  /// A Store app is running fullscreen
+  App,
+}
+
+impl NotificationState {
+  pub fn display_name(&self) -> &'static str {
+    match self {
+      NotificationState::NotPresent => "Not present",
+      NotificationState::Busy => "Busy",
+      NotificationState::D3dFullscreen => "Direct3D fullscreen",
+      NotificationState::PresentationMode => "Presentation mode",
+      NotificationState::AcceptsNotifications => "Accepts notifications",
+      NotificationState::QuietTime => "Quiet time",
+      NotificationState::App => "Store app",
+    }
+  }
+}
+
+impl fmt::Display for NotificationState {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.display_name())
+  }
+}
+
+/// Options of the notification state method.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationStateOptions {
+  /// States in which the foreground window counts as a game
+  #[serde(default = "default_game_states")]
+  pub states: Vec<NotificationState>,
+  /// Whether to count as a game when the state can't be queried
+  #[serde(default = "default_busy_on_error")]
+  pub busy_on_error: bool,
+}
+
+fn default_game_states() -> Vec<NotificationState> {
+  vec![NotificationState::Busy, NotificationState::D3dFullscreen]
+}
+
+fn default_busy_on_error() -> bool {
+  true
+}
+
+impl Default for NotificationStateOptions {
+  fn default() -> Self {
+    Self {
+      states: default_game_states(),
+      busy_on_error: default_busy_on_error(),
+    }
+  }
+}
+
+/// Which monitor a window has to cover to count as fullscreen.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FullscreenMonitor {
+  /// The monitor the window is on
+  #[default]
+  Current,
+  Primary,
+  /// A monitor by its device name, such as `\\.\DISPLAY2`
+  Device(String),
+}
+
+impl Serialize for FullscreenMonitor {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    match self {
+      FullscreenMonitor::Current => serializer.serialize_str("current"),
+      FullscreenMonitor::Primary => serializer.serialize_str("primary"),
+      FullscreenMonitor::Device(device) => serializer.serialize_str(device),
+    }
+  }
+}
+
+impl<'de> Deserialize<'de> for FullscreenMonitor {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let s = String::deserialize(deserializer)?;
+    Ok(match s.to_lowercase().as_str() {
+      "current" => FullscreenMonitor::Current,
+      "primary" => FullscreenMonitor::Primary,
+      _ => FullscreenMonitor::Device(s),
+    })
+  }
+}
+
+/// Options of the fullscreen method.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FullscreenOptions {
+  /// Window classes that never count as fullscreen, such as the desktop and
+  /// the taskbar
+  #[serde(default = "default_excluded_classes")]
+  pub excluded_classes: Vec<String>,
+  /// How many pixels the window may fall short of the monitor on each side
+  #[serde(default)]
+  pub tolerance: u32,
+  #[serde(default)]
+  pub monitor: FullscreenMonitor,
+}
+
+fn default_excluded_classes() -> Vec<String> {
+  [
+    // The desktop
+    "Progman",
+    "WorkerW",
+    // Taskbars
+    "Shell_TrayWnd",
+    "Shell_SecondaryTrayWnd",
+    // Start, search and notification flyouts
+    "Windows.UI.Core.CoreWindow",
+    // Alt+Tab and Task View
+    "MultitaskingViewFrame",
+    "XamlExplorerHostIslandWindow",
+    "ForegroundStaging",
+  ]
+  .map(String::from)
+  .to_vec()
+}
+
+impl Default for FullscreenOptions {
+  fn default() -> Self {
+    Self {
+      excluded_classes: default_excluded_classes(),
+      tolerance: 0,
+      monitor: FullscreenMonitor::default(),
+    }
+  }
+}
+
+/// Options of the input capture method: which signs of a game holding on to
+/// the mouse count. Either one is enough.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputCaptureOptions {
+  /// The cursor is confined to the foreground window with `ClipCursor`
+  #[serde(default = "default_true")]
+  pub clipped: bool,
+  /// The cursor is hidden while over the foreground window
+  #[serde(default = "default_true")]
+  pub hidden: bool,
+}
+
+fn default_true() -> bool {
+  true
+}
+
+impl Default for InputCaptureOptions {
+  fn default() -> Self {
+    Self {
+      clipped: true,
+      hidden: true,
+    }
+  }
+}
+
+/// Options of the key activity method: how many presses of which keys, in how
+/// long, look like someone playing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyActivityOptions {
+  #[serde(default = "default_activity_keys")]
+  pub keys: Vec<Key>,
+  #[serde(default = "default_activity_presses")]
+  pub presses: u32,
+  #[serde(default = "default_activity_window_ms")]
+  pub window_ms: u32,
+}
+
+fn default_activity_keys() -> Vec<Key> {
+  [
+    VK_W, VK_A, VK_S, VK_D, VK_UP, VK_DOWN, VK_LEFT, VK_RIGHT, VK_SPACE,
+  ]
+  .map(|vk| Key::vk(vk.0))
+  .to_vec()
+}
+
+fn default_activity_presses() -> u32 {
+  12
+}
+
+fn default_activity_window_ms() -> u32 {
+  3000
+}
+
+impl Default for KeyActivityOptions {
+  fn default() -> Self {
+    Self {
+      keys: default_activity_keys(),
+      presses: default_activity_presses(),
+      window_ms: default_activity_window_ms(),
+    }
+  }
+}
+
+/// How to tell whether the foreground window is a game: a single method such
+/// as `"fullscreen"`, a method with options such as
+/// `{"fullscreen": {"tolerance": 2}}`, or methods combined with
+/// `{"any": [...]}`, `{"all": [...]}` and `{"not": ...}`. Misspelled methods
+/// and options are rejected rather than left at their defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum Detection {
+  Method(DetectMethod),
+  NotificationState {
+    notification_state: NotificationStateOptions,
+  },
+  Fullscreen {
+    fullscreen: FullscreenOptions,
+  },
+  InputCapture {
+    input_capture: InputCaptureOptions,
+  },
+  KeyActivity {
+    key_activity: KeyActivityOptions,
+  },
+  Any {
+    any: Vec<Detection>,
+  },
+  All {
+    all: Vec<Detection>,
+  },
+  Not {
+    not: Box<Detection>,
+  },
+}
+
+impl Default for Detection {
+  fn default() -> Self {
+    Detection::Method(DetectMethod::default())
+  }
+}
+
+impl Detection {
+  /// The method, if this is a single one, with or without options.
+  pub fn method(&self) -> Option<DetectMethod> {
+    match self {
+      Detection::Method(method) => Some(*method),
+      Detection::NotificationState {
+        ..
+      } => Some(DetectMethod::NotificationState),
+      Detection::Fullscreen {
+        ..
+      } => Some(DetectMethod::Fullscreen),
+      Detection::InputCapture {
+        ..
+      } => Some(DetectMethod::InputCapture),
+      Detection::KeyActivity {
+        ..
+      } => Some(DetectMethod::KeyActivity),
+      Detection::Any {
+        ..
+      }
+      | Detection::All {
+        ..
+      }
+      | Detection::Not {
+        ..
+      } => None,
+    }
+  }
+
+  /// The longest time key presses are looked back over, or `None` if no key
+  /// activity method is used.
+  pub fn key_activity_window_ms(&self) -> Option<u32> {
+    match self {
+      Detection::Method(DetectMethod::KeyActivity) => Some(default_activity_window_ms()),
+      Detection::KeyActivity {
+        key_activity,
+      } => Some(key_activity.window_ms),
+      Detection::Any {
+        any: detections,
+      }
+      | Detection::All {
+        all: detections,
+      } => {
+        detections
+          .iter()
+          .filter_map(Detection::key_activity_window_ms)
+          .max()
+      },
+      Detection::Not {
+        not,
+      } => not.key_activity_window_ms(),
+      _ => None,
+    }
+  }
+}
+
+/// What window matchers know about a window.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowInfo {
+  pub title: String,
+  pub class: String,
+  /// Full path of the executable of the process that owns the window
+  pub path: String,
+  /// Package family name of that process, empty unless it is a packaged
+  /// (Store) app
+  pub package: String,
+  pub command_line: String,
+  /// Executable paths of the processes that started that process, its
+  /// parent's first
+  pub ancestors: Vec<String>,
+}
+
+impl WindowInfo {
+  pub const fn new() -> Self {
+    Self {
+      title: String::new(),
+      class: String::new(),
+      path: String::new(),
+      package: String::new(),
+      command_line: String::new(),
+      ancestors: Vec::new(),
+    }
+  }
+
+  /// File name of the executable, such as `game.exe`
+  pub fn exe(&self) -> &str {
+    file_name(&self.path)
+  }
+}
+
+fn file_name(path: &str) -> &str {
+  path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+fn fold_case(s: &str) -> impl Iterator<Item = char> + Clone + '_ {
+  s.chars().flat_map(char::to_lowercase)
+}
+
+/// Matches `text` against a glob where `*` stands for any run of characters
+/// and `?` for any single character.
+fn glob_matches(pattern: &[char], text: impl Iterator<Item = char> + Clone) -> bool {
+  let (mut pattern, mut text) = (pattern, text);
+  // Where to resume after the last `*` if the rest doesn't match
+  let mut backtrack = None;
+
+  loop {
+    let mut rest_of_text = text.clone();
+    match (pattern.split_first(), rest_of_text.next()) {
+      (Some((&'*', rest)), _) => {
+        backtrack = Some((rest, text.clone()));
+        pattern = rest;
+        continue;
+      },
+      (Some((&p, rest)), Some(c)) if p == '?' || p == c => {
+        pattern = rest;
+        text = rest_of_text;
+        continue;
+      },
+      (None, None) => return true,
+      _ => {},
+    }
+
+    // Let the last `*` swallow one more character and try again
+    let Some((rest, star_text)) = &mut backtrack else {
+      return false;
+    };
+    if star_text.next().is_none() {
+      return false;
+    }
+    pattern = rest;
+    text = star_text.clone();
+  }
+}
+
+#[derive(Debug, Clone)]
+enum PatternKind {
+  Exact(String),
+  Glob(Box<[char]>),
+  Regex(Regex),
+}
+
+/// A pattern for window titles, classes and executables. `glob:` selects a
+/// glob and `regex:` a regular expression, anything else has to match
+/// exactly. Patterns ignore case.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+  kind: PatternKind,
+  pub string_repr: String,
+}
+
+impl Pattern {
+  pub fn from_string(s: &str) -> Result<Self, String> {
+    let kind = if let Some(glob) = s.strip_prefix("glob:") {
+      PatternKind::Glob(fold_case(glob).collect())
+    } else if let Some(regex) = s.strip_prefix("regex:") {
+      let regex = RegexBuilder::new(regex)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("Invalid regex: {}", e))?;
+      PatternKind::Regex(regex)
+    } else {
+      PatternKind::Exact(fold_case(s).collect())
+    };
+
+    Ok(Self {
+      kind,
+      string_repr: s.to_string(),
+    })
+  }
+
+  pub fn matches(&self, text: &str) -> bool {
+    match &self.kind {
+      PatternKind::Exact(exact) => fold_case(text).eq(exact.chars()),
+      PatternKind::Glob(glob) => glob_matches(glob, fold_case(text)),
+      PatternKind::Regex(regex) => regex.is_match(text),
+    }
+  }
+}
+
+impl Serialize for Pattern {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(&self.string_repr)
+  }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let s = String::deserialize(deserializer)?;
+    Pattern::from_string(&s).map_err(serde::de::Error::custom)
+  }
+}
+
+/// Picks out windows by what we know about them. In the configuration a
+/// matcher is an object with a single field, such as `{"exe": "game.exe"}`
+/// or `{"not": {"class": "glob:Chrome_*"}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMatcher {
+  Title(Pattern),
+  Class(Pattern),
+  /// File name of the executable
+  Exe(Pattern),
+  /// Full path of the executable
+  Path(Pattern),
+  /// Package family name of a Store app, such as
+  /// `Microsoft.MinecraftUWP_8wekyb3d8bbwe`
+  Package(Pattern),
+  /// Command line the process was started with, including the executable
+  CommandLine(Pattern),
+  /// File name of the executable of any process the window's process
+  /// descends from, such as a launcher
+  Ancestor(Pattern),
+  /// Full path of the executable of any process the window's process
+  /// descends from
+  AncestorPath(Pattern),
+  Any(Vec<WindowMatcher>),
+  All(Vec<WindowMatcher>),
+  Not(Box<WindowMatcher>),
+}
+
+impl WindowMatcher {
+  pub fn matches(&self, window: &WindowInfo) -> bool {
+    match self {
+      WindowMatcher::Title(pattern) => pattern.matches(&window.title),
+      WindowMatcher::Class(pattern) => pattern.matches(&window.class),
+      WindowMatcher::Exe(pattern) => pattern.matches(window.exe()),
+      WindowMatcher::Path(pattern) => pattern.matches(&window.path),
+      WindowMatcher::Package(pattern) => pattern.matches(&window.package),
+      WindowMatcher::CommandLine(pattern) => pattern.matches(&window.command_line),
+      WindowMatcher::Ancestor(pattern) => {
+        window
+          .ancestors
+          .iter()
+          .any(|path| pattern.matches(file_name(path)))
+      },
+      WindowMatcher::AncestorPath(pattern) => {
+        window.ancestors.iter().any(|path| pattern.matches(path))
+      },
+      WindowMatcher::Any(matchers) => matchers.iter().any(|m| m.matches(window)),
+      WindowMatcher::All(matchers) => matchers.iter().all(|m| m.matches(window)),
+      WindowMatcher::Not(matcher) => !matcher.matches(window),
+    }
+  }
+}
+
+/// Delays that keep brief changes in detection from switching game mode on
+/// and off. All in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameModeTiming {
+  /// How long a window has to look like a game before keys are blocked
+  pub enter_delay_ms: u32,
+  /// How long a game's window has to stop looking like one before keys are
+  /// let through again
+  pub exit_delay_ms: u32,
+  /// How long game mode holds after the game's window loses the foreground
+  pub grace_period_ms: u32,
+}
+
+/// Rules for specific applications, used instead of the default lists while
+/// one of them is in the foreground.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+  pub name: String,
+  /// Executable names (`game.exe`) or full paths, compared case-insensitively
+  #[serde(default)]
+  pub executables: Vec<String>,
+  /// Also applies the profile to the windows this matches
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub window: Option<WindowMatcher>,
+  #[serde(default)]
+  pub blacklist: Vec<KeyCombo>,
+  #[serde(default)]
+  pub whitelist: Vec<KeyCombo>,
+  /// Overrides the default detect method
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub detect_method: Option<Detection>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyConfig {
   /// Key combinations that should be blocked
@@ -275,7 +1564,32 @@ pub struct KeyConfig {
   pub whitelist: Vec<KeyCombo>,
   /// Method used to detect if a window is a game
   #[serde(default)]
-  pub detect_method: DetectMethod,
+  pub detect_method: Detection,
+  /// Windows that are always treated as games, whatever the detect method
+  /// says
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub force_game: Vec<WindowMatcher>,
+  /// Windows that are never treated as games. Takes precedence over
+  /// `force_game`.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub never_game: Vec<WindowMatcher>,
+  #[serde(default)]
+  pub game_mode: GameModeTiming,
+  /// Maximum delay between the strokes of a chord, in milliseconds
+  #[serde(default = "default_chord_timeout_ms")]
+  pub chord_timeout_ms: u32,
+  /// Rewrite key combinations in their canonical form, and drop duplicates,
+  /// when saving
+  #[serde(default)]
+  pub normalize_on_save: bool,
+  /// Per-application rules. The top-level lists and detect method form the
+  /// default profile, used when no profile matches the foreground window.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub profiles: Vec<Profile>,
+}
+
+fn default_chord_timeout_ms() -> u32 {
+  1000
 }
 
 impl Default for KeyConfig {
@@ -287,36 +1601,34 @@ impl Default for KeyConfig {
       // Default: block Windows key by itself
       blacklist: vec![parse("lwin")],
       whitelist: vec![],
-      detect_method: DetectMethod::default(),
+      detect_method: Detection::default(),
+      force_game: vec![],
+      never_game: vec![],
+      game_mode: GameModeTiming::default(),
+      chord_timeout_ms: default_chord_timeout_ms(),
+      normalize_on_save: false,
+      profiles: vec![],
     }
   }
 }
 
 impl KeyConfig {
-  pub fn should_block(&self, key: u16, shift: bool, ctrl: bool, alt: bool, win: bool) -> bool {
-    // First check whitelist - if explicitly allowed, don't block
-    for combo in &self.whitelist {
-      if combo.matches(key, shift, ctrl, alt, win) {
-        return false;
-      }
-    }
+  /// Rewrites every key combination in its canonical form and drops entries
+  /// that repeat an earlier one in the same list.
+  pub fn normalize(&mut self) {
+    let profile_lists = self
+      .profiles
+      .iter_mut()
+      .flat_map(|profile| [&mut profile.blacklist, &mut profile.whitelist]);
 
-    // Then check blacklist - if explicitly blocked, block it
-    for combo in &self.blacklist {
-      //   println!(
-      //     r#"
-      //       combo: {}, {}, {}, {}, {}
-      //       key  : {}, {}, {}, {}, {}
-      //       "#,
-      //     combo.key, combo.shift, combo.ctrl, combo.alt, combo.win, key, shift, ctrl, alt, win
-      //   );
-      if combo.matches(key, shift, ctrl, alt, win) {
-        return true;
-      }
+    for list in [&mut self.blacklist, &mut self.whitelist]
+      .into_iter()
+      .chain(profile_lists)
+    {
+      let mut seen = HashSet::new();
+      list.retain(|combo| seen.insert(combo.clone()));
+      list.iter_mut().for_each(KeyCombo::normalize);
     }
-
-    // Default: don't block
-    false
   }
 
   pub fn load() -> Self {
@@ -351,7 +1663,15 @@ impl KeyConfig {
       let _ = fs::create_dir_all(parent);
     }
 
-    match serde_json::to_string_pretty(self) {
+    let content = if self.normalize_on_save {
+      let mut normalized = self.clone();
+      normalized.normalize();
+      serde_json::to_string_pretty(&normalized)
+    } else {
+      serde_json::to_string_pretty(self)
+    };
+
+    match content {
       Ok(content) => {
         if let Err(e) = fs::write(&config_path, content) {
           eprintln!("Failed to write config file: {}", e);
@@ -374,14 +1694,18 @@ impl KeyConfig {
 /// Dynamic config manager that supports live reloading
 #[derive(Debug)]
 pub struct ConfigManager {
-  config: Arc<RwLock<KeyConfig>>,
+  config: Arc<ActiveConfig>,
   // We don't store the watcher here to avoid Send/Sync issues
   // The watcher runs in its own thread
 }
 
 impl ConfigManager {
-  pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-    let config = Arc::new(RwLock::new(KeyConfig::load()));
+  /// Loads the configuration and watches it for changes, calling `on_reload`
+  /// from the watcher thread after each reload.
+  pub fn new(on_reload: impl Fn() + Send + 'static) -> Result<Self, Box<dyn std::error::Error>> {
+    let config = Arc::new(ActiveConfig::new(CompiledConfig::compile(
+      &KeyConfig::load(),
+    )));
     let config_clone = Arc::clone(&config);
 
     let (tx, rx) = mpsc::channel();
@@ -424,16 +1748,9 @@ impl ConfigManager {
                 // Small delay to ensure file write is complete
                 thread::sleep(Duration::from_millis(100));
 
-                match KeyConfig::load() {
-                  config => {
-                    if let Ok(mut current_config) = config_clone.write() {
-                      *current_config = config;
-                      println!("Configuration reloaded successfully");
-                    } else {
-                      eprintln!("Failed to acquire write lock for config reload");
-                    }
-                  },
-                }
+                config_clone.publish(CompiledConfig::compile(&KeyConfig::load()));
+                on_reload();
+                println!("Configuration reloaded successfully");
               }
             }
           },
@@ -447,25 +1764,380 @@ impl ConfigManager {
     })
   }
 
-  pub fn should_block(&self, key: u16, shift: bool, ctrl: bool, alt: bool, win: bool) -> bool {
-    if let Ok(config) = self.config.read() {
-      config.should_block(key, shift, ctrl, alt, win)
-    } else {
-      eprintln!("Failed to acquire read lock for config");
-      false // Default to not blocking if we can't read config
+  /// Runs `f` with the compiled form of the current configuration. This never
+  /// blocks or allocates, so it is safe to call from the keyboard hook.
+  pub fn with_config<R>(&self, f: impl FnOnce(&CompiledConfig) -> R) -> R {
+    self.config.read(f)
+  }
+
+  pub fn get_config_path(&self) -> PathBuf {
+    KeyConfig::config_path()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn every_key_name_round_trips_to_its_code() {
+    for &(name, vk) in KEY_NAMES {
+      assert_eq!(
+        KEY_NAMES.iter().filter(|&&(n, _)| n == name).count(),
+        1,
+        "'{}' is defined twice",
+        name
+      );
+
+      let combo = KeyCombo::from_string(name)
+        .unwrap_or_else(|err| panic!("'{}' failed to parse: {}", name, err));
+      assert_eq!(combo.strokes.len(), 1, "'{}'", name);
+      assert_eq!(combo.strokes[0].key, Some(Key::vk(vk)), "'{}'", name);
+
+      let upper = KeyCombo::from_string(&name.to_uppercase()).unwrap();
+      assert_eq!(upper.strokes[0].key, Some(Key::vk(vk)), "'{}'", name);
+    }
+
+    for &(name, code, extended) in EXTENDED_KEY_NAMES {
+      assert_eq!(key_code(name), None, "'{}' is defined twice", name);
+      let combo = KeyCombo::from_string(name).unwrap();
+      assert_eq!(
+        combo.strokes[0].key,
+        Some(Key::Vk {
+          code,
+          extended: Some(extended),
+        }),
+        "'{}'",
+        name
+      );
     }
   }
 
-  pub fn detect_method(&self) -> DetectMethod {
-    if let Ok(config) = self.config.read() {
-      config.detect_method
-    } else {
-      eprintln!("Failed to acquire read lock for config");
-      DetectMethod::default()
+  #[test]
+  fn new_key_families_are_named() {
+    let expected = [
+      ("numpad0", VK_NUMPAD0),
+      ("num9", VK_NUMPAD9),
+      ("numpadadd", VK_ADD),
+      ("divide", VK_DIVIDE),
+      ("playpause", VK_MEDIA_PLAY_PAUSE),
+      ("mute", VK_VOLUME_MUTE),
+      ("volumeup", VK_VOLUME_UP),
+      ("prevtrack", VK_MEDIA_PREV_TRACK),
+      ("browserback", VK_BROWSER_BACK),
+      ("browserhome", VK_BROWSER_HOME),
+      ("apps", VK_APPS),
+      ("menu", VK_APPS),
+      ("kana", VK_KANA),
+      ("hanja", VK_HANJA),
+      ("convert", VK_CONVERT),
+      ("imeon", VK_IME_ON),
+    ];
+    for (name, vk) in expected {
+      assert_eq!(key_code(name), Some(vk.0), "'{}'", name);
     }
   }
 
-  pub fn get_config_path(&self) -> PathBuf {
-    KeyConfig::config_path()
+  #[test]
+  fn raw_codes_parse() {
+    let key = |s: &str| KeyCombo::from_string(s).map(|combo| combo.strokes[0].key);
+
+    assert_eq!(key("vk:0x5B"), Ok(Some(Key::vk(0x5B))));
+    assert_eq!(key("vk:91"), Ok(Some(Key::vk(0x5B))));
+    assert_eq!(key("sc:0x15B"), Ok(Some(Key::Scan(0x15B))));
+    assert_eq!(key("SC:E05B"), Ok(Some(Key::Scan(0x15B))));
+    assert_eq!(key("ctrl+sc:0x1d"), Ok(Some(Key::Scan(0x1D))));
+    assert!(key("vk:0x100").is_err());
+    assert!(key("vk:").is_err());
+    assert!(key("sc:0x200").is_err());
+    assert!(key("sc:e0").is_err());
+  }
+
+  #[test]
+  fn scan_codes_respect_the_extended_flag() {
+    let key = KeyCombo::from_string("sc:e01d").unwrap().strokes[0]
+      .key
+      .unwrap();
+    let right_ctrl = KeyPress {
+      vk: 0xA3,
+      scan: 0x1D | EXTENDED_SCAN,
+    };
+    let left_ctrl = KeyPress {
+      vk: 0xA2,
+      scan: 0x1D,
+    };
+
+    assert!(key.matches(right_ctrl));
+    assert!(!key.matches(left_ctrl));
+  }
+
+  #[test]
+  fn extended_key_names_check_the_extended_flag() {
+    let main_enter = KeyPress {
+      vk: VK_RETURN.0,
+      scan: 0x1C,
+    };
+    let numpad_enter = KeyPress {
+      vk: VK_RETURN.0,
+      scan: 0x1C | EXTENDED_SCAN,
+    };
+    let matches = |combo: &str, press: KeyPress| {
+      let stroke = &KeyCombo::from_string(combo).unwrap().strokes[0];
+      stroke.key.unwrap().matches(press) && stroke.matches_modifiers(Modifiers::NONE)
+    };
+
+    assert!(matches("enter", main_enter));
+    assert!(matches("enter", numpad_enter));
+    assert!(matches("numpadenter", numpad_enter));
+    assert!(!matches("numpadenter", main_enter));
+    assert!(matches("mainenter", main_enter));
+    assert!(!matches("mainenter", numpad_enter));
+
+    let nav_home = KeyPress {
+      vk: VK_HOME.0,
+      scan: 0x47 | EXTENDED_SCAN,
+    };
+    let numpad_home = KeyPress {
+      vk: VK_HOME.0,
+      scan: 0x47,
+    };
+    assert!(matches("navhome", nav_home));
+    assert!(!matches("navhome", numpad_home));
+    assert!(!matches("ctrl+numpadhome", numpad_home));
+    assert!(matches("numpadhome", numpad_home));
+  }
+
+  #[test]
+  fn canonical_form_round_trips() {
+    let cases = [
+      ("Shift+LWIN", "shift+lwin"),
+      ("lwin + shift", "shift+lwin"),
+      ("super + shift", "shift+lwin"),
+      ("win+shift+ctrl+alt+RETURN", "ctrl+alt+shift+win+enter"),
+      ("RCtrl + LCtrl", "rctrl+lctrl"),
+      ("lctrl+rctrl+f1", "lctrl+rctrl+f1"),
+      ("f1+*", "*+f1"),
+      ("any+win", "win+*"),
+      ("lwin+*", "lwin+*"),
+      ("*+lwin", "*+lwin"),
+      ("vk:91", "lwin"),
+      ("vk:0xC1", "vk:0xC1"),
+      ("sc:e05b", "sc:0x15B"),
+      ("num5", "numpad5"),
+      ("hangul", "kana"),
+      ("NumpadEnter", "numpadenter"),
+      ("ctrl+k   ctrl + c", "ctrl+k ctrl+c"),
+      ("TAP:lalt", "tap:lalt"),
+    ];
+
+    for (input, canonical) in cases {
+      let combo = KeyCombo::from_string(input).unwrap();
+      assert_eq!(combo.to_string(), canonical, "'{}'", input);
+
+      let reparsed = KeyCombo::from_string(canonical).unwrap();
+      assert_eq!(reparsed, combo, "'{}'", input);
+      assert_eq!(reparsed.to_string(), canonical, "'{}'", input);
+    }
+  }
+
+  #[test]
+  fn chord_strokes_need_a_key_other_than_a_modifier() {
+    // Modifier keys pressed on their own never count as a chord stroke
+    assert!(KeyCombo::from_string("ctrl+k lctrl").is_err());
+    assert!(KeyCombo::from_string("lwin ctrl+c").is_err());
+    assert!(KeyCombo::from_string("ctrl+k sc:0x1d").is_err());
+    assert!(KeyCombo::from_string("ctrl+k vk:0x11").is_err());
+    // Held modifiers are fine, as is a modifier key as a whole rule
+    assert!(KeyCombo::from_string("ctrl+k ctrl+c").is_ok());
+    assert!(KeyCombo::from_string("ctrl+k ctrl+*").is_ok());
+    assert!(KeyCombo::from_string("lctrl").is_ok());
+  }
+
+  #[test]
+  fn single_keys_round_trip() {
+    let cases = [
+      ("W", "w"),
+      (" space ", "space"),
+      ("LShift", "lshift"),
+      ("navup", "navup"),
+      ("vk:0xC1", "vk:0xC1"),
+      ("sc:0x39", "sc:0x39"),
+    ];
+
+    for (input, canonical) in cases {
+      let key = Key::from_string(input).unwrap();
+      assert_eq!(key.to_string(), canonical, "'{}'", input);
+      assert_eq!(Key::from_string(canonical).unwrap(), key, "'{}'", input);
+    }
+    assert!(Key::from_string("shift").is_err());
+    assert!(Key::from_string("ctrl+w").is_err());
+  }
+
+  #[test]
+  fn key_activity_window_is_the_longest_in_use() {
+    let window = |json: &str| {
+      serde_json::from_str::<Detection>(json)
+        .unwrap()
+        .key_activity_window_ms()
+    };
+
+    assert_eq!(window(r#""fullscreen""#), None);
+    assert_eq!(window(r#""key_activity""#), Some(3000));
+    assert_eq!(
+      window(
+        r#"{"any": ["fullscreen", {"not": {"key_activity": {"window_ms": 5000}}}, "key_activity"]}"#
+      ),
+      Some(5000)
+    );
+  }
+
+  #[test]
+  fn single_methods_are_named_with_or_without_options() {
+    let method = |json: &str| serde_json::from_str::<Detection>(json).unwrap().method();
+
+    assert_eq!(method(r#""fullscreen""#), Some(DetectMethod::Fullscreen));
+    assert_eq!(
+      method(r#"{"notification_state": {"states": ["busy"]}}"#),
+      Some(DetectMethod::NotificationState)
+    );
+    assert_eq!(
+      method(r#"{"key_activity": {"presses": 4}}"#),
+      Some(DetectMethod::KeyActivity)
+    );
+    assert_eq!(method(r#"{"any": ["fullscreen"]}"#), None);
+    assert_eq!(method(r#"{"not": "fullscreen"}"#), None);
+  }
+
+  #[test]
+  fn combos_compare_by_meaning() {
+    let parse = |s: &str| KeyCombo::from_string(s).unwrap();
+    let hash = |combo: &KeyCombo| {
+      let mut hasher = std::collections::hash_map::DefaultHasher::new();
+      combo.hash(&mut hasher);
+      hasher.finish()
+    };
+
+    assert_eq!(parse("Shift+LWIN"), parse("lwin+shift"));
+    assert_eq!(hash(&parse("Shift+LWIN")), hash(&parse("lwin+shift")));
+    assert_eq!(parse("enter"), parse("return"));
+    assert_eq!(parse("shift+lwin"), parse("super+shift"));
+    assert_ne!(parse("shift+lwin"), parse("shift+rwin"));
+    assert_ne!(parse("lwin"), parse("tap:lwin"));
+    assert_ne!(parse("alt+f4"), parse("*+alt+f4"));
+  }
+
+  #[test]
+  fn normalize_rewrites_and_dedupes() {
+    let mut config = KeyConfig {
+      blacklist: ["Shift+LWIN", "lwin", "lwin+shift", "super + shift"]
+        .map(|s| KeyCombo::from_string(s).unwrap())
+        .to_vec(),
+      ..KeyConfig::default()
+    };
+    config.normalize();
+
+    let written: Vec<_> = config
+      .blacklist
+      .iter()
+      .map(|combo| combo.string_repr.as_str())
+      .collect();
+    assert_eq!(written, ["shift+lwin", "lwin"]);
+  }
+
+  fn window(title: &str, class: &str, path: &str) -> WindowInfo {
+    WindowInfo {
+      title: title.to_string(),
+      class: class.to_string(),
+      path: path.to_string(),
+      ..WindowInfo::new()
+    }
+  }
+
+  #[test]
+  fn patterns_match_exactly_by_glob_or_by_regex() {
+    let matches = |pattern: &str, text: &str| Pattern::from_string(pattern).unwrap().matches(text);
+
+    assert!(matches("Steam", "steam"));
+    assert!(!matches("Steam", "Steam Friends"));
+    assert!(matches("glob:*friends", "Steam Friends"));
+    assert!(matches("glob:steam*", "Steam"));
+    assert!(matches("glob:s?eam *s", "Steam Friends"));
+    assert!(matches("glob:*a*b*", "xaxxbx"));
+    assert!(!matches("glob:*a*b", "xaxxbx"));
+    assert!(!matches("glob:?", ""));
+    assert!(matches("regex:^Unity.*Class$", "UnityWndClass"));
+    assert!(!matches("regex:^Unity$", "UnityWndClass"));
+    assert!(Pattern::from_string("regex:(").is_err());
+  }
+
+  #[test]
+  fn window_matchers_check_each_property() {
+    let game = window("Game", "UnityWndClass", r"C:\Games\Game\Game.exe");
+    let matcher = |json: &str| serde_json::from_str::<WindowMatcher>(json).unwrap();
+
+    assert_eq!(game.exe(), "Game.exe");
+    assert!(matcher(r#"{"title": "game"}"#).matches(&game));
+    assert!(matcher(r#"{"class": "glob:Unity*"}"#).matches(&game));
+    assert!(matcher(r#"{"exe": "game.exe"}"#).matches(&game));
+    assert!(!matcher(r#"{"exe": "glob:*\\game.exe"}"#).matches(&game));
+    assert!(matcher(r#"{"path": "glob:c:\\games\\*"}"#).matches(&game));
+    assert!(!matcher(r#"{"package": "glob:?*"}"#).matches(&game));
+
+    let store_game = WindowInfo {
+      package: "Microsoft.MinecraftUWP_8wekyb3d8bbwe".to_string(),
+      ..window(
+        "Minecraft",
+        "ApplicationFrameWindow",
+        "Minecraft.Windows.exe",
+      )
+    };
+    assert!(matcher(r#"{"package": "glob:microsoft.minecraftuwp_*"}"#).matches(&store_game));
+  }
+
+  #[test]
+  fn window_matchers_look_at_the_process_tree() {
+    let league = WindowInfo {
+      command_line: r#""C:\Riot Games\League of Legends\Game\League of Legends.exe" -GameID=1"#
+        .to_string(),
+      ancestors: vec![
+        r"C:\Riot Games\League of Legends\LeagueClient.exe".to_string(),
+        r"C:\Riot Games\Riot Client\RiotClientServices.exe".to_string(),
+      ],
+      ..window(
+        "League of Legends (TM) Client",
+        "RiotWindowClass",
+        r"C:\Riot Games\League of Legends\Game\League of Legends.exe",
+      )
+    };
+    let matcher = |json: &str| serde_json::from_str::<WindowMatcher>(json).unwrap();
+
+    assert!(matcher(r#"{"ancestor": "riotclientservices.exe"}"#).matches(&league));
+    assert!(matcher(r#"{"ancestor_path": "glob:C:\\Riot Games\\*"}"#).matches(&league));
+    assert!(!matcher(r#"{"ancestor": "League of Legends.exe"}"#).matches(&league));
+    assert!(matcher(r#"{"command_line": "regex:-GameID=\\d+"}"#).matches(&league));
+    assert!(!matcher(r#"{"command_line": "-GameID=1"}"#).matches(&league));
+  }
+
+  #[test]
+  fn window_matchers_combine() {
+    let emulator = window(
+      "Mario - RetroArch",
+      "RetroArch",
+      r"C:\RetroArch\retroarch.exe",
+    );
+    let menu = window("RetroArch", "RetroArch", r"C:\RetroArch\retroarch.exe");
+    let matcher: WindowMatcher = serde_json::from_str(
+      r#"{"all": [
+        {"exe": "retroarch.exe"},
+        {"not": {"title": "RetroArch"}},
+        {"any": [{"class": "RetroArch"}, {"class": "SDL_app"}]}
+      ]}"#,
+    )
+    .unwrap();
+
+    assert!(matcher.matches(&emulator));
+    assert!(!matcher.matches(&menu));
+    assert!(!WindowMatcher::Any(vec![]).matches(&emulator));
+    assert!(WindowMatcher::All(vec![]).matches(&emulator));
   }
 }
@@ -1,7 +1,12 @@
-use crate::config::{DetectMethod, KeyCombo, KeyConfig};
+use crate::{
+  config::{DetectMethod, Detection, KeyCombo, KeyConfig, NotificationState},
+  detect::current_notification_state,
+};
 use iced::{
   Alignment, Application, Command, Element, Length, Settings, Theme,
-  widget::{Space, button, column, container, pick_list, row, scrollable, text, text_input},
+  widget::{
+    Space, button, checkbox, column, container, pick_list, row, scrollable, text, text_input,
+  },
 };
 use std::process;
 
@@ -14,6 +19,7 @@ pub enum Message {
   BlacklistKeyInput(String),
   WhitelistKeyInput(String),
   DetectMethodChanged(DetectMethod),
+  NormalizeOnSaveToggled(bool),
   ToggleHelp,
   Save,
   Cancel,
@@ -27,6 +33,7 @@ pub struct ConfigUI {
   whitelist_input: String,
   error_message: Option<String>,
   show_help: bool,
+  notification_state: Option<NotificationState>,
 }
 
 impl Application for ConfigUI {
@@ -35,7 +42,11 @@ impl Application for ConfigUI {
   type Message = Message;
   type Theme = Theme;
 
-  fn new(config: KeyConfig) -> (Self, Command<Message>) {
+  fn new(mut config: KeyConfig) -> (Self, Command<Message>) {
+    if config.normalize_on_save {
+      config.normalize();
+    }
+
     (
       ConfigUI {
         config,
@@ -43,6 +54,7 @@ impl Application for ConfigUI {
         whitelist_input: String::new(),
         error_message: None,
         show_help: false,
+        notification_state: current_notification_state(),
       },
       Command::none(),
     )
@@ -54,12 +66,19 @@ impl Application for ConfigUI {
 
   fn update(&mut self, message: Message) -> Command<Message> {
     self.error_message = None; // Clear error on each update
+    self.notification_state = current_notification_state();
 
     match message {
       Message::AddBlacklistKey => {
         if !self.blacklist_input.trim().is_empty() {
           match KeyCombo::from_string(&self.blacklist_input.trim()) {
-            Ok(key_combo) => {
+            Ok(key_combo) if self.config.blacklist.contains(&key_combo) => {
+              self.error_message = Some("That key combination is already in the list".to_string());
+            },
+            Ok(mut key_combo) => {
+              if self.config.normalize_on_save {
+                key_combo.normalize();
+              }
               self.config.blacklist.push(key_combo);
               self.blacklist_input.clear();
             },
@@ -72,7 +91,13 @@ impl Application for ConfigUI {
       Message::AddWhitelistKey => {
         if !self.whitelist_input.trim().is_empty() {
           match KeyCombo::from_string(&self.whitelist_input.trim()) {
-            Ok(key_combo) => {
+            Ok(key_combo) if self.config.whitelist.contains(&key_combo) => {
+              self.error_message = Some("That key combination is already in the list".to_string());
+            },
+            Ok(mut key_combo) => {
+              if self.config.normalize_on_save {
+                key_combo.normalize();
+              }
               self.config.whitelist.push(key_combo);
               self.whitelist_input.clear();
             },
@@ -99,7 +124,16 @@ impl Application for ConfigUI {
         self.whitelist_input = input;
       },
       Message::DetectMethodChanged(method) => {
-        self.config.detect_method = method;
+        // Picking the method already in use keeps its options
+        if self.config.detect_method.method() != Some(method) {
+          self.config.detect_method = Detection::Method(method);
+        }
+      },
+      Message::NormalizeOnSaveToggled(enabled) => {
+        self.config.normalize_on_save = enabled;
+        if enabled {
+          self.config.normalize();
+        }
       },
       Message::ToggleHelp => {
         self.show_help = !self.show_help;
@@ -144,10 +178,18 @@ impl Application for ConfigUI {
         column![
           text("How to enter key combinations").size(16),
           text("Use '+' between each modifier and key, e.g. 'ctrl+shift+esc'.").size(13),
-          text("Supported modifiers: 'ctrl', 'alt', 'shift', 'lwin', 'rwin'.").size(13),
+          text("Supported modifiers: 'ctrl', 'alt', 'shift', 'win'.").size(13),
+          text("Prefix a modifier with 'l' or 'r' to match one side only, e.g. 'rctrl+f12'.")
+            .size(13),
           text("Finish with exactly one key name like 'c', 'f12', 'space', 'delete', etc.").size(13),
           text("Names are case-insensitive; spaces around '+' are optional.").size(13),
+          text("Keys without a name can be given by code, e.g. 'vk:0x5B' or 'sc:e05b'.").size(13),
           text("Only one non-modifier key is allowed per combination.").size(13),
+          text("Use '*' to allow any other modifiers, e.g. '*+f1', or any key, e.g. 'win+*'.")
+            .size(13),
+          text("Separate strokes with spaces for a chord, e.g. 'ctrl+k ctrl+c'.").size(13),
+          text("Prefix with 'tap:' (e.g. 'tap:lwin') to only block a lone tap of the key.")
+            .size(13),
         ]
         .spacing(6),
       )
@@ -251,13 +293,33 @@ impl Application for ConfigUI {
     let detect_method_description =
       text("How to detect if the focused window is a game").size(12);
 
+    // Combinations can only be written in the configuration file
+    let selected_method = self.config.detect_method.method();
+
     let detect_method_picker = pick_list(
       DetectMethod::ALL.as_slice(),
-      Some(self.config.detect_method),
+      selected_method,
       Message::DetectMethodChanged,
     )
+    .placeholder("Combined methods (see configuration file)")
     .width(Length::Fill);
 
+    let notification_state = text(format!(
+      "Current notification state: {}",
+      self
+        .notification_state
+        .map_or("Unknown", |state| state.display_name())
+    ))
+    .size(12);
+
+    let normalize_checkbox = checkbox(
+      "Rewrite key combinations in a single spelling when saving",
+      self.config.normalize_on_save,
+      Message::NormalizeOnSaveToggled,
+    )
+    .size(16)
+    .text_size(13);
+
     // Error message
     let error_section: Element<Message> = if let Some(ref error) = self.error_message {
       text(error)
@@ -299,6 +361,9 @@ impl Application for ConfigUI {
       detect_method_description,
       Space::with_height(Length::Fixed(10.0)),
       detect_method_picker,
+      notification_state,
+      Space::with_height(Length::Fixed(20.0)),
+      normalize_checkbox,
       Space::with_height(Length::Fixed(20.0)),
       error_section,
       action_buttons,
@@ -0,0 +1,845 @@
+use std::{fmt, sync::Arc};
+
+use windows::{
+  Win32::{
+    Foundation::{HWND, LPARAM, POINT, RECT},
+    Graphics::Gdi::{
+      EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITOR_DEFAULTTONEAREST,
+      MONITOR_DEFAULTTOPRIMARY, MONITORINFO, MONITORINFOEXW, MonitorFromPoint, MonitorFromWindow,
+    },
+    System::SystemInformation::GetTickCount,
+    UI::{
+      HiDpi::{DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, SetThreadDpiAwarenessContext},
+      Shell::{
+        QUNS_ACCEPTS_NOTIFICATIONS, QUNS_APP, QUNS_BUSY, QUNS_NOT_PRESENT, QUNS_PRESENTATION_MODE,
+        QUNS_QUIET_TIME, QUNS_RUNNING_D3D_FULL_SCREEN, SHQueryUserNotificationState,
+      },
+      WindowsAndMessaging::{
+        CURSOR_SHOWING, CURSOR_SUPPRESSED, CURSORINFO, EnumChildWindows, GWL_STYLE, GetClassNameW,
+        GetClipCursor, GetCursorInfo, GetForegroundWindow, GetSystemMetrics, GetWindowLongPtrW,
+        GetWindowRect, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+        SM_YVIRTUALSCREEN, WS_CAPTION, WS_SYSMENU,
+      },
+    },
+  },
+  core::BOOL,
+};
+
+use crate::{
+  activity::{self, RecentPresses},
+  config::{
+    DetectMethod, Detection, FullscreenMonitor, FullscreenOptions, InputCaptureOptions, Key,
+    KeyActivityOptions, NotificationState, NotificationStateOptions, WindowInfo,
+  },
+  foreground,
+  library::GameLibrary,
+};
+
+/// Tells whether the foreground window is a game, so configured keys should
+/// be blocked.
+pub trait Detector: fmt::Debug + Send + Sync {
+  /// `window` is what we know about the foreground window.
+  fn is_game(&self, window: &WindowInfo) -> bool;
+}
+
+/// Builds the detector described by the configuration. `library` returns the
+/// installed games, so that scanning for them can be shared between detectors
+/// and skipped when none of them needs it.
+pub fn build(detection: &Detection, library: &dyn Fn() -> Arc<GameLibrary>) -> Box<dyn Detector> {
+  match detection {
+    Detection::Method(DetectMethod::NotificationState) => {
+      Box::new(NotificationStateDetector::new(
+        &NotificationStateOptions::default(),
+      ))
+    },
+    Detection::Method(DetectMethod::Fullscreen) => {
+      Box::new(Fullscreen::new(&FullscreenOptions::default()))
+    },
+    Detection::Method(DetectMethod::WindowStyle) => Box::new(WindowStyle),
+    Detection::Method(DetectMethod::GameLibrary) => Box::new(InLibrary(library())),
+    Detection::Method(DetectMethod::InputCapture) => {
+      Box::new(InputCapture::new(&InputCaptureOptions::default()))
+    },
+    Detection::Method(DetectMethod::KeyActivity) => {
+      Box::new(KeyActivity::new(&KeyActivityOptions::default()))
+    },
+    Detection::NotificationState {
+      notification_state,
+    } => Box::new(NotificationStateDetector::new(notification_state)),
+    Detection::Fullscreen {
+      fullscreen,
+    } => Box::new(Fullscreen::new(fullscreen)),
+    Detection::InputCapture {
+      input_capture,
+    } => Box::new(InputCapture::new(input_capture)),
+    Detection::KeyActivity {
+      key_activity,
+    } => Box::new(KeyActivity::new(key_activity)),
+    Detection::Any {
+      any,
+    } => Box::new(Any(any.iter().map(|d| build(d, library)).collect())),
+    Detection::All {
+      all,
+    } => Box::new(All(all.iter().map(|d| build(d, library)).collect())),
+    Detection::Not {
+      not,
+    } => Box::new(Not(build(not, library))),
+  }
+}
+
+/// The notification state Windows reports, or `None` if it can't be queried.
+pub fn current_notification_state() -> Option<NotificationState> {
+  let state = unsafe { SHQueryUserNotificationState() }.ok()?;
+  let state = match state {
+    QUNS_NOT_PRESENT => NotificationState::NotPresent,
+    QUNS_BUSY => NotificationState::Busy,
+    QUNS_RUNNING_D3D_FULL_SCREEN => NotificationState::D3dFullscreen,
+    QUNS_PRESENTATION_MODE => NotificationState::PresentationMode,
+    QUNS_ACCEPTS_NOTIFICATIONS => NotificationState::AcceptsNotifications,
+    QUNS_QUIET_TIME => NotificationState::QuietTime,
+    QUNS_APP => NotificationState::App,
+    _ => return None,
+  };
+  Some(state)
+}
+
+/// Windows reports one of the configured notification states.
+#[derive(Debug)]
+struct NotificationStateDetector {
+  states: Box<[NotificationState]>,
+  busy_on_error: bool,
+}
+
+impl NotificationStateDetector {
+  fn new(options: &NotificationStateOptions) -> Self {
+    Self {
+      states: options.states.clone().into_boxed_slice(),
+      busy_on_error: options.busy_on_error,
+    }
+  }
+
+  fn counts_as_game(&self, state: Option<NotificationState>) -> bool {
+    state.map_or(self.busy_on_error, |state| self.states.contains(&state))
+  }
+}
+
+impl Detector for NotificationStateDetector {
+  fn is_game(&self, _window: &WindowInfo) -> bool {
+    self.counts_as_game(current_notification_state())
+  }
+}
+
+/// Compares UTF-16 strings, ignoring the case of ASCII letters.
+fn eq_ignore_ascii_case(a: &[u16], b: &[u16]) -> bool {
+  let fold = |c: u16| {
+    if (0x41..=0x5A).contains(&c) {
+      c + 0x20
+    } else {
+      c
+    }
+  };
+  a.len() == b.len() && a.iter().zip(b).all(|(&a, &b)| fold(a) == fold(b))
+}
+
+/// `FullscreenMonitor` with device names in UTF-16, as Windows reports them.
+#[derive(Debug)]
+enum MonitorTarget {
+  Current,
+  Primary,
+  Device(Box<[u16]>),
+}
+
+/// The foreground window covers a whole monitor, and isn't part of the
+/// shell. For Store apps the app's own window is checked, not its frame.
+#[derive(Debug)]
+struct Fullscreen {
+  excluded_classes: Box<[Box<[u16]>]>,
+  tolerance: i32,
+  monitor: MonitorTarget,
+}
+
+impl Fullscreen {
+  fn new(options: &FullscreenOptions) -> Self {
+    Self {
+      excluded_classes: options
+        .excluded_classes
+        .iter()
+        .map(|class| class.encode_utf16().collect())
+        .collect(),
+      tolerance: options.tolerance.try_into().unwrap_or(i32::MAX),
+      monitor: match &options.monitor {
+        FullscreenMonitor::Current => MonitorTarget::Current,
+        FullscreenMonitor::Primary => MonitorTarget::Primary,
+        FullscreenMonitor::Device(device) => MonitorTarget::Device(device.encode_utf16().collect()),
+      },
+    }
+  }
+}
+
+impl Detector for Fullscreen {
+  fn is_game(&self, _window: &WindowInfo) -> bool {
+    let hwnd = foreground::content_window();
+    if hwnd.is_invalid() {
+      return false;
+    }
+
+    // Shell windows are told apart by the foreground window's class, since
+    // the window inside a Store app's frame has one of those classes too
+    let mut class = [0u16; 256];
+    let len = unsafe { GetClassNameW(GetForegroundWindow(), &mut class) };
+    let class = &class[..len.max(0) as usize];
+    if self
+      .excluded_classes
+      .iter()
+      .any(|excluded| eq_ignore_ascii_case(excluded, class))
+    {
+      return false;
+    }
+
+    in_physical_pixels(|| {
+      window_and_monitor_rects(hwnd, &self.monitor)
+        .is_some_and(|(window, monitor)| covers(window, monitor, self.tolerance))
+    })
+  }
+}
+
+/// Runs `f` measuring in physical pixels, so windows of DPI-aware games aren't
+/// compared with a monitor or cursor scaled for us, or the other way around.
+fn in_physical_pixels<R>(f: impl FnOnce() -> R) -> R {
+  let previous =
+    unsafe { SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) };
+  let result = f();
+  unsafe { SetThreadDpiAwarenessContext(previous) };
+  result
+}
+
+/// Whether `window` covers `monitor`, give or take `tolerance` pixels on each
+/// side.
+fn covers(window: RECT, monitor: RECT, tolerance: i32) -> bool {
+  window.left <= monitor.left + tolerance
+    && window.top <= monitor.top + tolerance
+    && window.right >= monitor.right - tolerance
+    && window.bottom >= monitor.bottom - tolerance
+}
+
+/// The foreground window holds on to the mouse: the cursor is clipped to it or
+/// hidden over it, as games that steer a camera with the mouse do. For Store
+/// apps the app's own window is checked, not its frame.
+#[derive(Debug)]
+struct InputCapture {
+  clipped: bool,
+  hidden: bool,
+}
+
+impl InputCapture {
+  fn new(options: &InputCaptureOptions) -> Self {
+    Self {
+      clipped: options.clipped,
+      hidden: options.hidden,
+    }
+  }
+}
+
+impl Detector for InputCapture {
+  fn is_game(&self, _window: &WindowInfo) -> bool {
+    let hwnd = foreground::content_window();
+    if hwnd.is_invalid() {
+      return false;
+    }
+
+    in_physical_pixels(|| {
+      let mut window = RECT::default();
+      if unsafe { GetWindowRect(hwnd, &mut window) }.is_err() {
+        return false;
+      }
+
+      let clipped =
+        self.clipped && cursor_clip().is_some_and(|clip| confines(clip, window, virtual_screen()));
+      let hidden = self.hidden && {
+        let mut cursor = CURSORINFO {
+          cbSize: std::mem::size_of::<CURSORINFO>() as u32,
+          ..Default::default()
+        };
+        unsafe { GetCursorInfo(&mut cursor) }.is_ok() && hides_cursor(&cursor, window)
+      };
+      clipped || hidden
+    })
+  }
+}
+
+/// The rectangle the cursor is confined to, which is the whole virtual screen
+/// when nothing confines it.
+pub fn cursor_clip() -> Option<RECT> {
+  let mut clip = RECT::default();
+  unsafe { GetClipCursor(&mut clip) }.ok()?;
+  Some(clip)
+}
+
+/// The rectangle around every monitor.
+fn virtual_screen() -> RECT {
+  unsafe {
+    let left = GetSystemMetrics(SM_XVIRTUALSCREEN);
+    let top = GetSystemMetrics(SM_YVIRTUALSCREEN);
+    RECT {
+      left,
+      top,
+      right: left + GetSystemMetrics(SM_CXVIRTUALSCREEN),
+      bottom: top + GetSystemMetrics(SM_CYVIRTUALSCREEN),
+    }
+  }
+}
+
+fn contains(outer: RECT, inner: RECT) -> bool {
+  inner.left >= outer.left
+    && inner.top >= outer.top
+    && inner.right <= outer.right
+    && inner.bottom <= outer.bottom
+}
+
+/// Whether the cursor `clip` keeps the cursor in `window`. A window covering
+/// the whole `screen` can't be told apart from no clip at all.
+fn confines(clip: RECT, window: RECT, screen: RECT) -> bool {
+  clip != screen && contains(window, clip)
+}
+
+/// Whether the cursor is hidden over `window`, rather than suppressed for
+/// touch or pen input or hidden somewhere else. Games hide it either with
+/// `ShowCursor`, or by setting no cursor at all, which still counts as showing.
+fn hides_cursor(cursor: &CURSORINFO, window: RECT) -> bool {
+  let position = cursor.ptScreenPos;
+  let point = RECT {
+    left: position.x,
+    top: position.y,
+    right: position.x,
+    bottom: position.y,
+  };
+  let showing = cursor.flags.0 & CURSOR_SHOWING.0 != 0 && !cursor.hCursor.is_invalid();
+  !showing && cursor.flags.0 & CURSOR_SUPPRESSED.0 == 0 && contains(window, point)
+}
+
+/// Enough presses of the configured keys in the last few seconds, as when
+/// moving around in a game.
+#[derive(Debug)]
+struct KeyActivity {
+  keys: Box<[Key]>,
+  presses: usize,
+  window_ms: u32,
+}
+
+impl KeyActivity {
+  fn new(options: &KeyActivityOptions) -> Self {
+    Self {
+      keys: options.keys.clone().into_boxed_slice(),
+      // No more presses than are remembered can ever be counted
+      presses: (options.presses as usize).clamp(1, activity::CAPACITY),
+      window_ms: options.window_ms,
+    }
+  }
+
+  fn counts_as_game(&self, presses: &RecentPresses, now: u32) -> bool {
+    presses.count(&self.keys, now, self.window_ms) >= self.presses
+  }
+}
+
+impl Detector for KeyActivity {
+  fn is_game(&self, _window: &WindowInfo) -> bool {
+    let now = unsafe { GetTickCount() };
+    foreground::with_recent_presses(|presses| self.counts_as_game(presses, now))
+  }
+}
+
+/// The foreground window has no caption, system menu or child windows. For
+/// Store apps the app's own window is checked, not its frame.
+#[derive(Debug)]
+struct WindowStyle;
+
+impl Detector for WindowStyle {
+  fn is_game(&self, _window: &WindowInfo) -> bool {
+    is_foreground_game_windowstyle()
+  }
+}
+
+/// The foreground window's executable is installed with a game from a
+/// launcher's library.
+#[derive(Debug)]
+struct InLibrary(Arc<GameLibrary>);
+
+impl Detector for InLibrary {
+  fn is_game(&self, window: &WindowInfo) -> bool {
+    self.0.contains(&window.path)
+  }
+}
+
+/// At least one of the detectors fires.
+#[derive(Debug)]
+struct Any(Box<[Box<dyn Detector>]>);
+
+impl Detector for Any {
+  fn is_game(&self, window: &WindowInfo) -> bool {
+    self.0.iter().any(|detector| detector.is_game(window))
+  }
+}
+
+/// Every detector fires.
+#[derive(Debug)]
+struct All(Box<[Box<dyn Detector>]>);
+
+impl Detector for All {
+  fn is_game(&self, window: &WindowInfo) -> bool {
+    self.0.iter().all(|detector| detector.is_game(window))
+  }
+}
+
+/// The detector doesn't fire.
+#[derive(Debug)]
+struct Not(Box<dyn Detector>);
+
+impl Detector for Not {
+  fn is_game(&self, window: &WindowInfo) -> bool {
+    !self.0.is_game(window)
+  }
+}
+
+extern "system" fn enum_child_cb(_hwnd: HWND, lparam: LPARAM) -> BOOL {
+  unsafe {
+    // lparam points to our counter
+    let counter = &mut *(lparam.0 as *mut u32);
+    *counter += 1;
+  }
+  // TRUE = continue enumeration
+  BOOL(1)
+}
+
+pub fn count_child_windows(hwnd: HWND) -> u32 {
+  let mut count: u32 = 0;
+
+  unsafe {
+    _ = EnumChildWindows(
+      Some(hwnd),
+      Some(enum_child_cb),
+      LPARAM(&mut count as *mut _ as isize),
+    );
+  }
+
+  count
+}
+
+fn is_foreground_game_windowstyle() -> bool {
+  unsafe {
+    let hwnd = foreground::content_window();
+    if hwnd.is_invalid() {
+      return false;
+    }
+
+    let style = GetWindowLongPtrW(hwnd, GWL_STYLE) as u32;
+    let looks_like_game = (style & WS_SYSMENU.0) == 0 && (style & WS_CAPTION.0) == 0;
+    if !looks_like_game {
+      return false;
+    }
+
+    if count_child_windows(hwnd) > 0 {
+      return false;
+    }
+  }
+
+  true
+}
+
+fn monitor_rect(hmon: HMONITOR) -> Option<RECT> {
+  let mut mi = MONITORINFO {
+    cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+    ..Default::default()
+  };
+
+  unsafe { GetMonitorInfoW(hmon, &mut mi) }
+    .as_bool()
+    .then_some(mi.rcMonitor)
+}
+
+/// What `find_monitor_cb` looks for, and what it found.
+struct MonitorSearch<'a> {
+  device: &'a [u16],
+  rect: Option<RECT>,
+}
+
+extern "system" fn find_monitor_cb(
+  hmon: HMONITOR,
+  _hdc: HDC,
+  _rect: *mut RECT,
+  lparam: LPARAM,
+) -> BOOL {
+  // lparam points to our search
+  let search = unsafe { &mut *(lparam.0 as *mut MonitorSearch) };
+
+  let mut mi = MONITORINFOEXW::default();
+  mi.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+  if !unsafe { GetMonitorInfoW(hmon, &mut mi as *mut _ as *mut MONITORINFO) }.as_bool() {
+    return BOOL(1);
+  }
+
+  let len = mi
+    .szDevice
+    .iter()
+    .position(|&c| c == 0)
+    .unwrap_or(mi.szDevice.len());
+  if eq_ignore_ascii_case(&mi.szDevice[..len], search.device) {
+    search.rect = Some(mi.monitorInfo.rcMonitor);
+    // FALSE = stop enumerating
+    return BOOL(0);
+  }
+
+  BOOL(1)
+}
+
+fn window_and_monitor_rects(hwnd: HWND, monitor: &MonitorTarget) -> Option<(RECT, RECT)> {
+  let mut window = RECT::default();
+  unsafe { GetWindowRect(hwnd, &mut window) }.ok()?;
+
+  let monitor = match monitor {
+    MonitorTarget::Current => {
+      monitor_rect(unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) })?
+    },
+    MonitorTarget::Primary => {
+      // The primary monitor is the one at the origin
+      monitor_rect(unsafe { MonitorFromPoint(POINT::default(), MONITOR_DEFAULTTOPRIMARY) })?
+    },
+    MonitorTarget::Device(device) => {
+      let mut search = MonitorSearch {
+        device,
+        rect: None,
+      };
+      unsafe {
+        _ = EnumDisplayMonitors(
+          None,
+          None,
+          Some(find_monitor_cb),
+          LPARAM(&mut search as *mut _ as isize),
+        );
+      }
+      search.rect?
+    },
+  };
+
+  Some((window, monitor))
+}
+
+#[cfg(test)]
+mod tests {
+  use windows::Win32::UI::WindowsAndMessaging::{CURSORINFO_FLAGS, HCURSOR};
+
+  use super::*;
+  use crate::config::KeyPress;
+
+  #[derive(Debug)]
+  struct Fixed(bool);
+
+  impl Detector for Fixed {
+    fn is_game(&self, _window: &WindowInfo) -> bool {
+      self.0
+    }
+  }
+
+  fn fixed(results: &[bool]) -> Box<[Box<dyn Detector>]> {
+    results
+      .iter()
+      .map(|&result| Box::new(Fixed(result)) as Box<dyn Detector>)
+      .collect()
+  }
+
+  #[test]
+  fn combinators_combine_their_detectors() {
+    let window = WindowInfo::new();
+    assert!(Any(fixed(&[false, true])).is_game(&window));
+    assert!(!Any(fixed(&[false, false])).is_game(&window));
+    assert!(!Any(fixed(&[])).is_game(&window));
+    assert!(All(fixed(&[true, true])).is_game(&window));
+    assert!(!All(fixed(&[true, false])).is_game(&window));
+    assert!(Not(Box::new(Fixed(false))).is_game(&window));
+  }
+
+  #[test]
+  fn library_games_are_found_by_path() {
+    let mut library = GameLibrary::default();
+    library.add(r"D:\SteamLibrary\steamapps\common\ELDEN RING");
+    let detector = InLibrary(Arc::new(library));
+    let exe = |path: &str| {
+      WindowInfo {
+        path: path.to_string(),
+        ..WindowInfo::new()
+      }
+    };
+
+    assert!(detector.is_game(&exe(
+      r"D:\SteamLibrary\steamapps\common\ELDEN RING\Game\eldenring.exe"
+    )));
+    assert!(!detector.is_game(&exe(r"C:\Program Files\Mozilla Firefox\firefox.exe")));
+    assert!(!detector.is_game(&WindowInfo::new()));
+    assert_eq!(
+      serde_json::from_str::<DetectMethod>(r#""game_library""#).unwrap(),
+      DetectMethod::GameLibrary
+    );
+  }
+
+  #[test]
+  fn detection_is_read_as_a_name_or_a_combination() {
+    let detection = |json: &str| serde_json::from_str::<Detection>(json).unwrap();
+    assert_eq!(
+      detection(r#""window_style""#),
+      Detection::Method(DetectMethod::WindowStyle)
+    );
+    assert_eq!(
+      detection(r#""fullscreen""#),
+      Detection::Method(DetectMethod::Fullscreen)
+    );
+    assert!(serde_json::from_str::<Detection>(r#""sometimes""#).is_err());
+
+    // The library is the one detector that doesn't ask Windows
+    let library = || {
+      let mut library = GameLibrary::default();
+      library.add(r"C:\Games");
+      Arc::new(library)
+    };
+    let window = |path: &str| {
+      WindowInfo {
+        path: path.to_string(),
+        ..WindowInfo::new()
+      }
+    };
+    let game = window(r"C:\Games\game.exe");
+    let app = window(r"C:\Other\app.exe");
+    let cases = [
+      (r#"{"any": ["game_library"]}"#, true, false),
+      (r#"{"not": "game_library"}"#, false, true),
+      (
+        r#"{"any": ["game_library", {"not": "game_library"}]}"#,
+        true,
+        true,
+      ),
+      (
+        r#"{"all": ["game_library", {"not": "game_library"}]}"#,
+        false,
+        false,
+      ),
+    ];
+    for (json, is_game, is_app) in cases {
+      let detector = build(&detection(json), &library);
+      assert_eq!(detector.is_game(&game), is_game, "{}", json);
+      assert_eq!(detector.is_game(&app), is_app, "{}", json);
+    }
+  }
+
+  #[test]
+  fn misspelled_detection_is_rejected() {
+    let misspelled = [
+      r#"{"fullscreen": {"tolerence": 2}}"#,
+      r#"{"notification_state": {"state": ["busy"]}}"#,
+      r#"{"input_capture": {"clip": false}}"#,
+      r#"{"key_activity": {"window": 1000}}"#,
+      r#"{"any": ["fullscreen"], "al": ["window_style"]}"#,
+      r#"{"fullscreen": {}, "not": "window_style"}"#,
+      r#"{"fulscreen": {}}"#,
+    ];
+    for json in misspelled {
+      assert!(serde_json::from_str::<Detection>(json).is_err(), "{}", json);
+    }
+
+    let detection: Detection = serde_json::from_str(r#"{"fullscreen": {"tolerance": 2}}"#).unwrap();
+    assert!(matches!(detection, Detection::Fullscreen { .. }));
+  }
+
+  #[test]
+  fn notification_states_are_configurable() {
+    let detector = |json: &str| {
+      let options: NotificationStateOptions = serde_json::from_str(json).unwrap();
+      NotificationStateDetector::new(&options)
+    };
+
+    let default = detector("{}");
+    assert!(default.counts_as_game(Some(NotificationState::Busy)));
+    assert!(default.counts_as_game(Some(NotificationState::D3dFullscreen)));
+    assert!(!default.counts_as_game(Some(NotificationState::PresentationMode)));
+    assert!(default.counts_as_game(None));
+
+    let custom = detector(r#"{"states": ["presentation_mode", "app"], "busy_on_error": false}"#);
+    assert!(custom.counts_as_game(Some(NotificationState::App)));
+    assert!(!custom.counts_as_game(Some(NotificationState::Busy)));
+    assert!(!custom.counts_as_game(None));
+  }
+
+  #[test]
+  fn notification_state_options_go_with_the_method() {
+    let detection: Detection =
+      serde_json::from_str(r#"{"notification_state": {"states": ["quiet_time"]}}"#).unwrap();
+
+    assert_eq!(
+      detection,
+      Detection::NotificationState {
+        notification_state: NotificationStateOptions {
+          states: vec![NotificationState::QuietTime],
+          busy_on_error: true,
+        },
+      }
+    );
+  }
+
+  fn rect(left: i32, top: i32, right: i32, bottom: i32) -> RECT {
+    RECT {
+      left,
+      top,
+      right,
+      bottom,
+    }
+  }
+
+  #[test]
+  fn fullscreen_allows_for_the_tolerance() {
+    let monitor = rect(1920, 0, 3840, 1080);
+
+    assert!(covers(rect(1920, 0, 3840, 1080), monitor, 0));
+    assert!(covers(rect(1912, -8, 3848, 1088), monitor, 0));
+    assert!(!covers(rect(1921, 0, 3839, 1080), monitor, 0));
+    assert!(covers(rect(1921, 0, 3839, 1080), monitor, 1));
+    assert!(!covers(rect(1920, 40, 3840, 1080), monitor, 8));
+  }
+
+  #[test]
+  fn input_capture_needs_the_cursor_in_the_window() {
+    let screen = rect(0, 0, 3840, 1080);
+    let window = rect(100, 100, 900, 700);
+
+    assert!(confines(rect(108, 131, 892, 692), window, screen));
+    assert!(confines(rect(500, 400, 500, 400), window, screen));
+    assert!(!confines(screen, window, screen));
+    assert!(!confines(rect(0, 0, 1920, 1080), window, screen));
+    // A window covering every monitor looks the same clipped or not
+    assert!(!confines(screen, screen, screen));
+
+    let inside = POINT {
+      x: 500,
+      y: 400,
+    };
+    let outside = POINT {
+      x: 1000,
+      y: 400,
+    };
+    let arrow = HCURSOR(1 as _);
+    let cursor = |flags, handle, position| {
+      CURSORINFO {
+        flags,
+        hCursor: handle,
+        ptScreenPos: position,
+        ..Default::default()
+      }
+    };
+    assert!(hides_cursor(
+      &cursor(CURSORINFO_FLAGS(0), arrow, inside),
+      window
+    ));
+    assert!(!hides_cursor(
+      &cursor(CURSORINFO_FLAGS(0), arrow, outside),
+      window
+    ));
+    assert!(!hides_cursor(
+      &cursor(CURSOR_SHOWING, arrow, inside),
+      window
+    ));
+    assert!(!hides_cursor(
+      &cursor(CURSOR_SUPPRESSED, arrow, inside),
+      window
+    ));
+    // SetCursor(NULL) leaves the cursor showing, with nothing to show
+    assert!(hides_cursor(
+      &cursor(CURSOR_SHOWING, HCURSOR::default(), inside),
+      window
+    ));
+    assert!(!hides_cursor(
+      &cursor(CURSOR_SHOWING, HCURSOR::default(), outside),
+      window
+    ));
+  }
+
+  #[test]
+  fn input_capture_signs_can_be_turned_off() {
+    let detection: Detection =
+      serde_json::from_str(r#"{"input_capture": {"hidden": false}}"#).unwrap();
+    assert_eq!(
+      detection,
+      Detection::InputCapture {
+        input_capture: InputCaptureOptions {
+          clipped: true,
+          hidden: false,
+        },
+      }
+    );
+    // Both signs count unless turned off
+    assert_eq!(
+      serde_json::from_str::<Detection>(r#"{"input_capture": {}}"#).unwrap(),
+      Detection::InputCapture {
+        input_capture: InputCaptureOptions {
+          clipped: true,
+          hidden: true,
+        },
+      }
+    );
+  }
+
+  #[test]
+  fn key_activity_needs_enough_recent_presses() {
+    let options: KeyActivityOptions =
+      serde_json::from_str(r#"{"keys": ["W", "lshift", "sc:0x39"], "presses": 3}"#).unwrap();
+    assert_eq!(options.window_ms, 3000);
+    let detector = KeyActivity::new(&options);
+
+    let press = |vk, scan| {
+      KeyPress {
+        vk,
+        scan,
+      }
+    };
+    let (w, shift, space, e) = (
+      press(0x57, 0x11),
+      press(0xA0, 0x2A),
+      press(0x20, 0x39),
+      press(0x45, 0x12),
+    );
+    let mut presses = RecentPresses::new();
+    for (key, time) in [(w, 1000), (e, 1200), (shift, 1500), (e, 2000)] {
+      presses.record(key, time);
+    }
+    assert!(!detector.counts_as_game(&presses, 2500));
+
+    presses.record(space, 3000);
+    assert!(detector.counts_as_game(&presses, 3000));
+    assert!(detector.counts_as_game(&presses, 4000));
+    assert!(!detector.counts_as_game(&presses, 4001));
+
+    let defaults = KeyActivity::new(&KeyActivityOptions::default());
+    let mut presses = RecentPresses::new();
+    for time in 0..12 {
+      presses.record(w, time * 250);
+    }
+    assert!(defaults.counts_as_game(&presses, 3000));
+    assert!(!defaults.counts_as_game(&presses, 3500));
+  }
+
+  #[test]
+  fn fullscreen_options_have_shell_defaults() {
+    let options: FullscreenOptions =
+      serde_json::from_str(r#"{"tolerance": 4, "monitor": "\\\\.\\DISPLAY2"}"#).unwrap();
+
+    assert!(
+      options
+        .excluded_classes
+        .iter()
+        .any(|class| class == "Progman")
+    );
+    assert_eq!(options.tolerance, 4);
+    assert_eq!(
+      options.monitor,
+      FullscreenMonitor::Device(r"\\.\DISPLAY2".to_string())
+    );
+
+    let primary: FullscreenOptions = serde_json::from_str(r#"{"monitor": "primary"}"#).unwrap();
+    assert_eq!(primary.monitor, FullscreenMonitor::Primary);
+  }
+}
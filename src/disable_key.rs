@@ -1,32 +1,57 @@
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::cell::Cell;
 
 use once_cell::sync::OnceCell;
 use windows::{
   Win32::{
-    Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
-    Graphics::Gdi::{GetMonitorInfoW, MONITOR_DEFAULTTONEAREST, MONITORINFO, MonitorFromWindow},
+    Foundation::{LPARAM, LRESULT, WPARAM},
     UI::{
       Input::KeyboardAndMouse::{
-        VIRTUAL_KEY, VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MENU, VK_RCONTROL,
-        VK_RMENU, VK_RSHIFT, VK_RWIN, VK_SHIFT,
+        GetAsyncKeyState, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT,
+        KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, SendInput, VIRTUAL_KEY, VK_LWIN, VK_RWIN,
       },
-      Shell::{QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN, SHQueryUserNotificationState},
       WindowsAndMessaging::{
-        CallNextHookEx, EnumChildWindows, GWL_STYLE, GetForegroundWindow, GetWindowLongPtrW,
-        GetWindowRect, HHOOK, KBDLLHOOKSTRUCT, SetWindowsHookExW, UnhookWindowsHookEx,
-        WH_KEYBOARD_LL, WM_KEYDOWN, WM_SYSKEYDOWN, WS_CAPTION, WS_SYSMENU,
+        CallNextHookEx, GetForegroundWindow, HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED,
+        SetWindowsHookExW, UnhookWindowsHookEx, WH_KEYBOARD_LL, WM_KEYDOWN, WM_KEYUP,
+        WM_SYSKEYDOWN, WM_SYSKEYUP,
       },
     },
   },
-  core::{BOOL, Error as WinError},
+  core::Error as WinError,
 };
 
-use crate::config::{ConfigManager, DetectMethod};
+use crate::{
+  config::{ConfigManager, EXTENDED_SCAN, KeyPress},
+  foreground,
+  modifiers::{ModifierTracker, Modifiers},
+  presses::PressTracker,
+  rules::ChordTracker,
+};
+
+// The low-level hook always runs on the thread that installed it, so its
+// private state lives in thread locals instead of shared atomics.
+thread_local! {
+  static MODIFIER_TRACKER: Cell<ModifierTracker> = const { Cell::new(ModifierTracker::new()) };
+  static PRESS_TRACKER: Cell<PressTracker> = const { Cell::new(PressTracker::new()) };
+  static LAST_FOREGROUND: Cell<isize> = const { Cell::new(0) };
+  static PENDING_TAP: Cell<Option<u16>> = const { Cell::new(None) };
+  static CHORD_TRACKER: Cell<ChordTracker> = const { Cell::new(ChordTracker::new()) };
+}
 
-static SHIFT_DOWN: AtomicBool = AtomicBool::new(false);
-static CTRL_DOWN: AtomicBool = AtomicBool::new(false);
-static ALT_DOWN: AtomicBool = AtomicBool::new(false);
-static WIN_DOWN: AtomicBool = AtomicBool::new(false);
+/// Tags input we inject so the hook passes it through untouched.
+const INJECTED_MARKER: usize = 0x574b_4c4b;
+
+/// Unassigned virtual key sent between a lone tap and its release. The shell
+/// only opens the Start menu (and apps only activate their menu bar) when
+/// nothing else was pressed while the Windows or Alt key was held.
+const MASK_KEY: VIRTUAL_KEY = VIRTUAL_KEY(0xE8);
+
+/// What to do with a new key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+  Pass,
+  Block,
+  MaskTap,
+}
 
 #[derive(Debug)]
 struct UnsafePtr {
@@ -39,77 +64,136 @@ unsafe impl Sync for UnsafePtr {}
 static KEYBOARD_HOOK: OnceCell<UnsafePtr> = OnceCell::new();
 static CONFIG_MANAGER: OnceCell<ConfigManager> = OnceCell::new();
 
-extern "system" fn enum_child_cb(_hwnd: HWND, lparam: LPARAM) -> BOOL {
-  unsafe {
-    // lparam points to our counter
-    let counter = &mut *(lparam.0 as *mut u32);
-    *counter += 1;
-  }
-  // TRUE = continue enumeration
-  BOOL(1)
+fn is_key_physically_down(vk: u16) -> bool {
+  unsafe { (GetAsyncKeyState(vk as i32) as u16 & 0x8000) != 0 }
 }
 
-pub fn count_child_windows(hwnd: HWND) -> u32 {
-  let mut count: u32 = 0;
-
-  unsafe {
-    _ = EnumChildWindows(
-      Some(hwnd),
-      Some(enum_child_cb),
-      LPARAM(&mut count as *mut _ as isize),
-    );
+/// Resyncs hook state from the real keyboard whenever the foreground window
+/// changes (including to and from the lock screen), since key-ups may have
+/// been delivered elsewhere while we weren't looking.
+fn sync_with_foreground() {
+  let foreground = unsafe { GetForegroundWindow() }.0 as isize;
+  if LAST_FOREGROUND.replace(foreground) == foreground {
+    return;
   }
 
-  count
+  let presses = PRESS_TRACKER.with(|cell| {
+    let mut presses = cell.get();
+    presses.forget_released(is_key_physically_down);
+    cell.set(presses);
+    presses
+  });
+  // Swallowed modifiers read as up even while held, so the press tracker
+  // knows better than the keyboard state for those
+  MODIFIER_TRACKER.with(|cell| {
+    let mut tracker = cell.get();
+    tracker.resync(|vk| presses.is_swallowed(vk) || is_key_physically_down(vk));
+    cell.set(tracker);
+  });
+  CHORD_TRACKER.with(|cell| {
+    let mut chords = cell.get();
+    chords.reset();
+    cell.set(chords);
+  });
 }
 
-fn is_foreground_game_windowstyle() -> bool {
-  unsafe {
-    let hwnd = GetForegroundWindow();
-    if hwnd.is_invalid() {
-      return false;
-    }
+fn track_modifiers(vk: u16, is_keydown: bool) -> Modifiers {
+  MODIFIER_TRACKER.with(|cell| {
+    let mut tracker = cell.get();
+    let mods = tracker.update(vk, is_keydown);
+    cell.set(tracker);
+    mods
+  })
+}
 
-    let style = GetWindowLongPtrW(hwnd, GWL_STYLE) as u32;
-    let looks_like_game = (style & WS_SYSMENU.0) == 0 && (style & WS_CAPTION.0) == 0;
-    if !looks_like_game {
-      return false;
-    }
+/// Records a new key press for chord matching and returns the updated
+/// history.
+fn track_chords(key: KeyPress, mods: Modifiers, time: u32, timeout_ms: u32) -> ChordTracker {
+  CHORD_TRACKER.with(|cell| {
+    let mut chords = cell.get();
+    chords.push(key, mods, time, timeout_ms);
+    cell.set(chords);
+    chords
+  })
+}
+
+/// Decides what to do with a new key press right now.
+fn judge_press(key: KeyPress, mods: Modifiers, time: u32) -> Verdict {
+  if let Some(config_manager) = CONFIG_MANAGER.get() {
+    config_manager.with_config(|config| {
+      let chords = track_chords(key, mods, time, config.chord_timeout_ms);
+      // Right after a reload, detection hasn't yet worked out which of the new
+      // profiles applies, so keys pass until it has
+      let Some(rules) = foreground::profile().and_then(|profile| config.rules(profile)) else {
+        return Verdict::Pass;
+      };
+
+      let verdict = if rules.should_block(key, mods, &chords) {
+        Verdict::Block
+      } else if rules.should_suppress_tap(key, mods, &chords) {
+        Verdict::MaskTap
+      } else {
+        Verdict::Pass
+      };
+      if verdict == Verdict::Pass {
+        return Verdict::Pass;
+      }
 
-    if count_child_windows(hwnd) > 0 {
-      return false;
+      // Detection runs as window events come in rather than here, so the hook
+      // doesn't have to ask Windows for anything
+      if foreground::is_game() {
+        verdict
+      } else {
+        Verdict::Pass
+      }
+    })
+  } else {
+    // Fallback to old behavior if config is not available
+    let is_win_key = key.vk == VK_LWIN.0 || key.vk == VK_RWIN.0;
+    let is_plain = !mods.intersects(Modifiers::SHIFT | Modifiers::CTRL | Modifiers::ALT);
+    if is_win_key && is_plain && foreground::is_game() {
+      return Verdict::Block;
     }
+    Verdict::Pass
   }
-
-  true
 }
 
-pub fn is_foreground_fullscreen() -> bool {
-  unsafe {
-    let hwnd = GetForegroundWindow();
-    if hwnd.is_invalid() {
-      return false;
-    }
-
-    let mut win_rect = RECT::default();
-    if !GetWindowRect(hwnd, &mut win_rect).is_ok() {
-      return false;
-    }
+fn keyboard_input(vk: VIRTUAL_KEY, scan: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+  INPUT {
+    r#type: INPUT_KEYBOARD,
+    Anonymous: INPUT_0 {
+      ki: KEYBDINPUT {
+        wVk: vk,
+        wScan: scan,
+        dwFlags: flags,
+        time: 0,
+        dwExtraInfo: INJECTED_MARKER,
+      },
+    },
+  }
+}
 
-    let hmon = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST);
-    let mut mi = MONITORINFO {
-      cbSize: std::mem::size_of::<MONITORINFO>() as u32,
-      ..Default::default()
-    };
+/// Replays the release of a lone tap with the mask key pressed in front of it.
+/// The real release has to be swallowed and re-sent, because input injected
+/// from inside the hook is queued after the event being processed.
+fn release_with_mask(ev: &KBDLLHOOKSTRUCT) {
+  let mut release_flags = KEYEVENTF_KEYUP;
+  if ev.flags.contains(LLKHF_EXTENDED) {
+    release_flags |= KEYEVENTF_EXTENDEDKEY;
+  }
 
-    if !GetMonitorInfoW(hmon, &mut mi).as_bool() {
-      return false;
-    }
+  let inputs = [
+    keyboard_input(MASK_KEY, 0, KEYBD_EVENT_FLAGS(0)),
+    keyboard_input(MASK_KEY, 0, KEYEVENTF_KEYUP),
+    keyboard_input(
+      VIRTUAL_KEY(ev.vkCode as u16),
+      ev.scanCode as u16,
+      release_flags,
+    ),
+  ];
 
-    win_rect.left <= mi.rcMonitor.left
-      && win_rect.top <= mi.rcMonitor.top
-      && win_rect.right >= mi.rcMonitor.right
-      && win_rect.bottom >= mi.rcMonitor.bottom
+  unsafe {
+    SendInput(&inputs, std::mem::size_of::<INPUT>() as i32);
   }
 }
 
@@ -120,58 +204,59 @@ extern "system" fn keyboard_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> L
 
   let ev = unsafe { *(lparam.0 as *const KBDLLHOOKSTRUCT) };
   let vk = ev.vkCode as u16;
+  let mut key = KeyPress {
+    vk,
+    scan: ev.scanCode as u16 & 0xFF,
+  };
+  if ev.flags.contains(LLKHF_EXTENDED) {
+    key.scan |= EXTENDED_SCAN;
+  }
   let msg = wparam.0 as u32;
 
-  let is_keydown = msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN;
-  // let is_keyup = msg == WM_KEYUP || msg == WM_SYSKEYUP;
-
-  // Update modifier key states
-  match VIRTUAL_KEY(vk) {
-    VK_LSHIFT | VK_RSHIFT | VK_SHIFT => SHIFT_DOWN.store(is_keydown, Ordering::Relaxed),
-    VK_LCONTROL | VK_RCONTROL | VK_CONTROL => CTRL_DOWN.store(is_keydown, Ordering::Relaxed),
-    VK_LMENU | VK_RMENU | VK_MENU => ALT_DOWN.store(is_keydown, Ordering::Relaxed),
-    VK_LWIN | VK_RWIN => WIN_DOWN.store(is_keydown, Ordering::Relaxed),
-    _ => {},
-  };
+  if ev.dwExtraInfo == INJECTED_MARKER {
+    return unsafe { CallNextHookEx(None, code, wparam, lparam) };
+  }
 
-  let shift = SHIFT_DOWN.load(Ordering::Relaxed);
-  let ctrl = CTRL_DOWN.load(Ordering::Relaxed);
-  let alt = ALT_DOWN.load(Ordering::Relaxed);
-  let win = WIN_DOWN.load(Ordering::Relaxed);
-
-  // Only check configuration for key down events
-  if is_keydown {
-    if let Some(config_manager) = CONFIG_MANAGER.get() {
-      if config_manager.should_block(vk, shift, ctrl, alt, win) {
-        match config_manager.detect_method() {
-          DetectMethod::NotificationState => {
-            let state = unsafe { SHQueryUserNotificationState().unwrap_or(QUNS_BUSY) };
-            if state == QUNS_BUSY || state == QUNS_RUNNING_D3D_FULL_SCREEN {
-              return LRESULT(1);
-            }
-          },
-          DetectMethod::Fullscreen => {
-            if is_foreground_fullscreen() {
-              return LRESULT(1);
-            }
-          },
-          DetectMethod::WindowStyle => {
-            if is_foreground_game_windowstyle() {
-              return LRESULT(1);
-            }
-          },
-        };
+  let is_keydown = msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN;
+  let is_keyup = msg == WM_KEYUP || msg == WM_SYSKEYUP;
+
+  sync_with_foreground();
+
+  // Update modifier key states, tracking each side separately
+  let mods = track_modifiers(vk, is_keydown);
+
+  // A press is handled as a unit: the configuration is only consulted when a
+  // key first goes down, and its auto-repeats and key-up follow that decision
+  let swallow = PRESS_TRACKER.with(|cell| {
+    let mut presses = cell.get();
+    let swallow = if is_keydown {
+      presses.repeat(vk).unwrap_or_else(|| {
+        foreground::key_pressed(key, ev.time);
+        let verdict = judge_press(key, mods, ev.time);
+        // Any other key pressed in the meantime turns a pending tap into a
+        // regular combination like Win+D, which is left alone
+        PENDING_TAP.set((verdict == Verdict::MaskTap).then_some(vk));
+        presses.press(vk, verdict == Verdict::Block);
+        verdict == Verdict::Block
+      })
+    } else if is_keyup {
+      let swallow = presses.release(vk);
+      if !swallow && PENDING_TAP.get() == Some(vk) {
+        PENDING_TAP.set(None);
+        release_with_mask(&ev);
+        true
+      } else {
+        swallow
       }
     } else {
-      // Fallback to old behavior if config is not available
-      let is_win_key = vk == VK_LWIN.0 || vk == VK_RWIN.0;
-      if is_win_key && !(shift || ctrl || alt) {
-        let state = unsafe { SHQueryUserNotificationState().unwrap_or(QUNS_BUSY) };
-        if state == QUNS_BUSY || state == QUNS_RUNNING_D3D_FULL_SCREEN {
-          return LRESULT(1);
-        }
-      }
-    }
+      false
+    };
+    cell.set(presses);
+    swallow
+  });
+
+  if swallow {
+    return LRESULT(1);
   }
 
   unsafe {
@@ -186,7 +271,7 @@ extern "system" fn keyboard_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> L
 
 pub(crate) fn attach() {
   // Initialize configuration manager with file watching
-  match ConfigManager::new() {
+  match ConfigManager::new(foreground::invalidate) {
     Ok(config_manager) => {
       CONFIG_MANAGER.set(config_manager).unwrap();
       println!("Configuration manager initialized with file watching");
@@ -197,6 +282,9 @@ pub(crate) fn attach() {
     },
   }
 
+  // Detection runs on this thread too, whenever a window event comes in
+  foreground::attach(CONFIG_MANAGER.get());
+
   let hhk = unsafe { SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_hook), None, 0) };
   KEYBOARD_HOOK
     .set(UnsafePtr {
@@ -206,6 +294,8 @@ pub(crate) fn attach() {
 }
 
 pub(crate) fn detach() -> Result<(), WinError> {
+  foreground::detach();
+
   unsafe {
     UnhookWindowsHookEx(HHOOK(KEYBOARD_HOOK.get().unwrap().ptr))?;
   }
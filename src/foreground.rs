@@ -0,0 +1,426 @@
+use std::{
+  cell::{Cell, RefCell},
+  sync::atomic::{AtomicIsize, Ordering},
+};
+
+use windows::{
+  Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+    System::{LibraryLoader::GetModuleHandleW, SystemInformation::GetTickCount},
+    UI::{
+      Accessibility::{HWINEVENTHOOK, SetWinEventHook, UnhookWinEvent},
+      Shell::{QUNS_BUSY, QUNS_RUNNING_D3D_FULL_SCREEN, SHQueryUserNotificationState},
+      WindowsAndMessaging::{
+        CHILDID_SELF, CreateWindowExW, DefWindowProcW, DestroyWindow, EVENT_OBJECT_HIDE,
+        EVENT_OBJECT_LOCATIONCHANGE, EVENT_OBJECT_NAMECHANGE, EVENT_OBJECT_SHOW,
+        EVENT_SYSTEM_FOREGROUND, EVENT_SYSTEM_MINIMIZEEND, EVENT_SYSTEM_MINIMIZESTART,
+        EnumChildWindows, GetClassNameW, GetForegroundWindow, GetWindowTextW,
+        GetWindowThreadProcessId, KillTimer, OBJID_CURSOR, OBJID_WINDOW, PostMessageW,
+        RegisterClassW, SetTimer, WINDOW_EX_STYLE, WINDOW_STYLE, WINEVENT_OUTOFCONTEXT, WM_APP,
+        WM_DISPLAYCHANGE, WM_TIMER, WNDCLASSW,
+      },
+    },
+  },
+  core::{BOOL, Error as WinError},
+};
+use windows_strings::w;
+
+use crate::{
+  activity::RecentPresses,
+  config::{ConfigManager, GameModeTiming, KeyPress, WindowInfo},
+  detect,
+  game_state::GameState,
+  process::ProcessCache,
+  rules::ProfileMatch,
+};
+
+// WinEvent hooks are delivered through the message loop of the thread that
+// set them, which is the thread the keyboard hook runs on. Everything the
+// keyboard hook needs to know about the foreground window is worked out here
+// ahead of time, so the hook itself never has to ask Windows.
+thread_local! {
+  static CONFIG_MANAGER: Cell<Option<&'static ConfigManager>> = const { Cell::new(None) };
+  static FOREGROUND: Cell<isize> = const { Cell::new(0) };
+  static FOREGROUND_WINDOW: RefCell<WindowInfo> = const { RefCell::new(WindowInfo::new()) };
+  static CONTENT_WINDOW: Cell<isize> = const { Cell::new(0) };
+  static GAME_STATE: Cell<GameState> = const { Cell::new(GameState::new()) };
+  static PROFILE: Cell<Option<ProfileMatch>> = const { Cell::new(None) };
+  static EVENT_HOOKS: RefCell<Vec<HWINEVENTHOOK>> = const { RefCell::new(Vec::new()) };
+  static PROCESSES: RefCell<ProcessCache> = RefCell::new(ProcessCache::default());
+  static CURSOR_CLIP: Cell<Option<RECT>> = const { Cell::new(None) };
+  static RECENT_PRESSES: RefCell<RecentPresses> = const { RefCell::new(RecentPresses::new()) };
+  static ACTIVITY_TIMER_SET: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Hidden window that receives display changes, our timer and requests to
+/// run detection again. Other threads post to it, so it can't be thread local.
+static MESSAGE_WINDOW: AtomicIsize = AtomicIsize::new(0);
+
+/// Posted to the hidden window to run detection again.
+const WM_REFRESH: u32 = WM_APP + 1;
+
+/// Timer that fires when the game state is due to change on its own.
+const DEADLINE_TIMER: usize = 1;
+
+/// Timer that runs detection again while a detector counts key presses, so it
+/// notices when typing starts to look like playing and when it stops.
+const ACTIVITY_TIMER: usize = 2;
+const ACTIVITY_INTERVAL_MS: u32 = 250;
+
+/// Ranges of window events that can change whether the foreground window is a
+/// game.
+const EVENT_RANGES: [(u32, u32); 5] = [
+  (EVENT_SYSTEM_FOREGROUND, EVENT_SYSTEM_FOREGROUND),
+  (EVENT_SYSTEM_MINIMIZESTART, EVENT_SYSTEM_MINIMIZEEND),
+  (EVENT_OBJECT_SHOW, EVENT_OBJECT_HIDE),
+  (EVENT_OBJECT_LOCATIONCHANGE, EVENT_OBJECT_LOCATIONCHANGE),
+  (EVENT_OBJECT_NAMECHANGE, EVENT_OBJECT_NAMECHANGE),
+];
+
+// Longest title and class name read from the foreground window, in UTF-16
+// code units
+const TITLE_LEN: usize = 512;
+const CLASS_LEN: usize = 256;
+
+/// Hosts the frame windows of Store apps, with the app's own window as a
+/// child.
+const FRAME_HOST_EXE: &str = "ApplicationFrameHost.exe";
+
+fn push_utf16(dest: &mut String, units: &[u16]) {
+  dest.extend(
+    char::decode_utf16(units.iter().copied()).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+  );
+}
+
+/// What `find_hosted_app` is looking for and what it found.
+struct HostedAppSearch {
+  frame_pid: u32,
+  app: Option<(HWND, u32)>,
+}
+
+extern "system" fn find_hosted_app_cb(hwnd: HWND, lparam: LPARAM) -> BOOL {
+  let search = unsafe { &mut *(lparam.0 as *mut HostedAppSearch) };
+  let mut pid = 0;
+  unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+  if pid != 0 && pid != search.frame_pid {
+    search.app = Some((hwnd, pid));
+    return BOOL(0);
+  }
+  BOOL(1)
+}
+
+/// Finds the window and process of the app hosted in a Store app frame: the
+/// first child window that doesn't belong to the frame host itself. There is
+/// none while the app is starting up or suspended.
+fn find_hosted_app(frame: HWND, frame_pid: u32) -> Option<(HWND, u32)> {
+  let mut search = HostedAppSearch {
+    frame_pid,
+    app: None,
+  };
+  unsafe {
+    _ = EnumChildWindows(
+      Some(frame),
+      Some(find_hosted_app_cb),
+      LPARAM(&mut search as *mut _ as isize),
+    );
+  }
+  search.app
+}
+
+/// Reads what window matchers need to know about `hwnd`. For Store apps the
+/// process is the app rather than the frame host. Returns the window that
+/// shows the content, which is the app's own window for Store apps.
+fn read_window_info(hwnd: HWND, window: &mut WindowInfo) -> HWND {
+  window.title.clear();
+  window.class.clear();
+
+  let mut title = [0u16; TITLE_LEN];
+  let len = unsafe { GetWindowTextW(hwnd, &mut title) };
+  push_utf16(&mut window.title, &title[..len.max(0) as usize]);
+
+  let mut class = [0u16; CLASS_LEN];
+  let len = unsafe { GetClassNameW(hwnd, &mut class) };
+  push_utf16(&mut window.class, &class[..len.max(0) as usize]);
+
+  let mut pid = 0;
+  unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+
+  PROCESSES.with_borrow_mut(|processes| {
+    processes.read_into(pid, window);
+    if window.exe().eq_ignore_ascii_case(FRAME_HOST_EXE)
+      && let Some((app_window, app_pid)) = find_hosted_app(hwnd, pid)
+    {
+      processes.read_into(app_pid, window);
+      return app_window;
+    }
+    hwnd
+  })
+}
+
+/// The old behavior, for when there is no configuration.
+fn is_busy() -> bool {
+  let state = unsafe { SHQueryUserNotificationState().unwrap_or(QUNS_BUSY) };
+  state == QUNS_BUSY || state == QUNS_RUNNING_D3D_FULL_SCREEN
+}
+
+/// Runs detection on the foreground window and moves the game state along.
+/// What we know about the window is read again when it is a different window
+/// or when `reread` is set.
+fn refresh(reread: bool) {
+  let now = unsafe { GetTickCount() };
+  let hwnd = unsafe { GetForegroundWindow() };
+  let foreground = hwnd.0 as isize;
+  if FOREGROUND.replace(foreground) != foreground || reread {
+    let content = FOREGROUND_WINDOW.with_borrow_mut(|window| read_window_info(hwnd, window));
+    CONTENT_WINDOW.set(content.0 as isize);
+  }
+
+  let (state, profile, deadline, key_activity_window) = match CONFIG_MANAGER.get() {
+    Some(config_manager) => {
+      config_manager.with_config(|config| {
+        let (profile, detected) = FOREGROUND_WINDOW.with_borrow(|window| {
+          let profile = config.profile_for(window);
+          // The profile was just found in this configuration, so it has rules
+          let detected = config.game_override(window).unwrap_or_else(|| {
+            config
+              .rules(profile)
+              .is_some_and(|rules| rules.detector.is_game(window))
+          });
+          (profile, detected)
+        });
+        let state = GAME_STATE
+          .get()
+          .update(config.game_mode, now, foreground, detected);
+        (
+          state,
+          Some(profile),
+          state.deadline(config.game_mode),
+          config.key_activity_window_ms,
+        )
+      })
+    },
+    None => {
+      let state = GAME_STATE
+        .get()
+        .update(GameModeTiming::default(), now, foreground, is_busy());
+      (state, None, None, None)
+    },
+  };
+  GAME_STATE.set(state);
+  PROFILE.set(profile);
+
+  let window = MESSAGE_WINDOW.load(Ordering::Acquire);
+  if window == 0 {
+    return;
+  }
+  let window = HWND(window as _);
+  match deadline {
+    Some(deadline) => unsafe {
+      SetTimer(
+        Some(window),
+        DEADLINE_TIMER,
+        deadline.wrapping_sub(now),
+        None,
+      );
+    },
+    None => {
+      let _ = unsafe { KillTimer(Some(window), DEADLINE_TIMER) };
+    },
+  }
+
+  // Key presses aren't reported to us as window events, and the keyboard hook
+  // can't afford to start the timer, so it runs as long as presses matter
+  set_activity_timer(window, key_activity_window.is_some());
+}
+
+/// Starts or stops the activity timer. Restarting it would put off its next
+/// tick, so it is left alone while it runs.
+fn set_activity_timer(window: HWND, run: bool) {
+  if ACTIVITY_TIMER_SET.replace(run) == run {
+    return;
+  }
+  if run {
+    unsafe { SetTimer(Some(window), ACTIVITY_TIMER, ACTIVITY_INTERVAL_MS, None) };
+  } else {
+    let _ = unsafe { KillTimer(Some(window), ACTIVITY_TIMER) };
+  }
+}
+
+unsafe extern "system" fn win_event_hook(
+  _hook: HWINEVENTHOOK,
+  event: u32,
+  hwnd: HWND,
+  id_object: i32,
+  id_child: i32,
+  _event_thread: u32,
+  _event_time: u32,
+) {
+  if event == EVENT_SYSTEM_FOREGROUND
+    || event == EVENT_SYSTEM_MINIMIZESTART
+    || event == EVENT_SYSTEM_MINIMIZEEND
+  {
+    refresh(false);
+    return;
+  }
+
+  if id_object == OBJID_CURSOR.0 {
+    // Showing or hiding the cursor is reported, but clipping it isn't, so
+    // that is checked for as the cursor moves
+    let clip_changed = event == EVENT_OBJECT_LOCATIONCHANGE && {
+      let clip = detect::cursor_clip();
+      CURSOR_CLIP.replace(clip) != clip
+    };
+    if event != EVENT_OBJECT_LOCATIONCHANGE || clip_changed {
+      refresh(false);
+    }
+    return;
+  }
+
+  // Other changes are reported for every object on the desktop, down to the
+  // caret, so only the foreground window itself is of interest
+  let is_foreground = id_object == OBJID_WINDOW.0
+    && id_child == CHILDID_SELF as i32
+    && hwnd.0 as isize == FOREGROUND.get();
+  if is_foreground {
+    refresh(event == EVENT_OBJECT_NAMECHANGE);
+  }
+}
+
+extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+  match msg {
+    WM_DISPLAYCHANGE | WM_TIMER | WM_REFRESH => {
+      refresh(false);
+      LRESULT(0)
+    },
+    _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+  }
+}
+
+/// Creates the hidden window. It is a regular top-level window rather than a
+/// message-only one, because those don't receive broadcasts like
+/// `WM_DISPLAYCHANGE`.
+fn create_message_window() -> Result<HWND, WinError> {
+  let instance = unsafe { GetModuleHandleW(None)? };
+  let class_name = w!("winkeylock_foreground");
+  let class = WNDCLASSW {
+    lpfnWndProc: Some(window_proc),
+    hInstance: instance.into(),
+    lpszClassName: class_name,
+    ..Default::default()
+  };
+  if unsafe { RegisterClassW(&class) } == 0 {
+    return Err(WinError::from_thread());
+  }
+
+  unsafe {
+    CreateWindowExW(
+      WINDOW_EX_STYLE(0),
+      class_name,
+      w!(""),
+      WINDOW_STYLE(0),
+      0,
+      0,
+      0,
+      0,
+      None,
+      None,
+      Some(instance.into()),
+      None,
+    )
+  }
+}
+
+/// Whether keys should be blocked as if a game were running.
+pub fn is_game() -> bool {
+  GAME_STATE.get().is_game()
+}
+
+/// Records a new key press from the keyboard hook. Detection only looks at it
+/// on its next run, at most `ACTIVITY_INTERVAL_MS` later.
+pub fn key_pressed(key: KeyPress, time: u32) {
+  RECENT_PRESSES.with_borrow_mut(|presses| presses.record(key, time));
+}
+
+/// Runs `f` with the recent key presses. Only detectors running on the
+/// keyboard hook's thread see them.
+pub fn with_recent_presses<R>(f: impl FnOnce(&RecentPresses) -> R) -> R {
+  RECENT_PRESSES.with_borrow(f)
+}
+
+/// The profile that applies to the foreground window, as of the last
+/// detection run, or `None` before detection has run with a configuration.
+/// See `CompiledConfig::rules`.
+pub fn profile() -> Option<ProfileMatch> {
+  PROFILE.get()
+}
+
+/// The window detectors should look at the style and size of: the foreground
+/// window, or the app's own window if that is a Store app frame.
+pub fn content_window() -> HWND {
+  HWND(CONTENT_WINDOW.get() as _)
+}
+
+/// Asks for detection to run again, for changes no window event reports such
+/// as a new configuration. Safe to call from any thread.
+pub fn invalidate() {
+  let window = MESSAGE_WINDOW.load(Ordering::Acquire);
+  if window != 0 {
+    let _ = unsafe { PostMessageW(Some(HWND(window as _)), WM_REFRESH, WPARAM(0), LPARAM(0)) };
+  }
+}
+
+/// Starts tracking the foreground window. Must be called on the thread that
+/// runs the keyboard hook and the message loop.
+pub fn attach(config_manager: Option<&'static ConfigManager>) {
+  CONFIG_MANAGER.set(config_manager);
+
+  match create_message_window() {
+    Ok(window) => MESSAGE_WINDOW.store(window.0 as isize, Ordering::Release),
+    Err(e) => {
+      eprintln!("Failed to create window for display changes: {}", e);
+      eprintln!("Continuing without display change notifications or game mode delays");
+    },
+  }
+
+  EVENT_HOOKS.with_borrow_mut(|hooks| {
+    for (min, max) in EVENT_RANGES {
+      let hook = unsafe {
+        SetWinEventHook(
+          min,
+          max,
+          None,
+          Some(win_event_hook),
+          0,
+          0,
+          WINEVENT_OUTOFCONTEXT,
+        )
+      };
+      if hook.is_invalid() {
+        eprintln!("Failed to hook window events {:#x}-{:#x}", min, max);
+      } else {
+        hooks.push(hook);
+      }
+    }
+  });
+
+  refresh(true);
+}
+
+pub fn detach() {
+  EVENT_HOOKS.with_borrow_mut(|hooks| {
+    for hook in hooks.drain(..) {
+      let _ = unsafe { UnhookWinEvent(hook) };
+    }
+  });
+
+  ACTIVITY_TIMER_SET.set(false);
+  let window = MESSAGE_WINDOW.swap(0, Ordering::AcqRel);
+  if window != 0 {
+    let window = HWND(window as _);
+    unsafe {
+      let _ = KillTimer(Some(window), DEADLINE_TIMER);
+      let _ = KillTimer(Some(window), ACTIVITY_TIMER);
+      let _ = DestroyWindow(window);
+    }
+  }
+}
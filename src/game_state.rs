@@ -0,0 +1,264 @@
+use crate::config::GameModeTiming;
+
+/// Milliseconds from `since` to `now` on the wrapping system tick count.
+fn elapsed(now: u32, since: u32) -> u32 {
+  now.wrapping_sub(since)
+}
+
+/// Whether game mode is on, with the timestamps needed to ride out brief
+/// changes in detection. Windows are identified by their handle and times
+/// are system tick counts, in milliseconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+  #[default]
+  Idle,
+  /// `window` has looked like a game since `since`, but not for long enough
+  Entering { window: isize, since: u32 },
+  /// Game mode is on for `window`
+  Active { window: isize },
+  /// The game's window stopped looking like a game at `since`
+  Leaving { window: isize, since: u32 },
+  /// The game's window lost the foreground at `since`
+  Grace { since: u32 },
+}
+
+impl GameState {
+  pub const fn new() -> Self {
+    GameState::Idle
+  }
+
+  /// Whether keys should be blocked as if a game were running.
+  pub fn is_game(self) -> bool {
+    matches!(
+      self,
+      GameState::Active { .. } | GameState::Leaving { .. } | GameState::Grace { .. }
+    )
+  }
+
+  /// When the state next changes on its own if detection stays the same.
+  pub fn deadline(self, timing: GameModeTiming) -> Option<u32> {
+    match self {
+      GameState::Idle
+      | GameState::Active {
+        ..
+      } => None,
+      GameState::Entering {
+        since, ..
+      } => Some(since.wrapping_add(timing.enter_delay_ms)),
+      GameState::Leaving {
+        since, ..
+      } => Some(since.wrapping_add(timing.exit_delay_ms)),
+      GameState::Grace {
+        since,
+      } => Some(since.wrapping_add(timing.grace_period_ms)),
+    }
+  }
+
+  /// Moves on given whether the foreground `window` looks like a game at
+  /// `now`.
+  pub fn update(self, timing: GameModeTiming, now: u32, window: isize, detected: bool) -> Self {
+    match self {
+      GameState::Idle if !detected => GameState::Idle,
+      GameState::Idle if timing.enter_delay_ms == 0 => {
+        GameState::Active {
+          window,
+        }
+      },
+      GameState::Idle => {
+        GameState::Entering {
+          window,
+          since: now,
+        }
+      },
+      GameState::Entering {
+        window: entering,
+        since,
+      } => {
+        if !detected {
+          GameState::Idle
+        } else if entering != window {
+          // Another window has to wait out the delay on its own
+          GameState::Idle.update(timing, now, window, detected)
+        } else if elapsed(now, since) >= timing.enter_delay_ms {
+          GameState::Active {
+            window,
+          }
+        } else {
+          self
+        }
+      },
+      GameState::Active {
+        window: game,
+      }
+      | GameState::Leaving {
+        window: game, ..
+      } if game != window && timing.grace_period_ms > 0 => {
+        GameState::Grace {
+          since: now,
+        }
+        .update(timing, now, window, detected)
+      },
+      GameState::Active {
+        ..
+      }
+      | GameState::Leaving {
+        ..
+      } if detected => {
+        GameState::Active {
+          window,
+        }
+      },
+      GameState::Active {
+        ..
+      } => {
+        GameState::Leaving {
+          window,
+          since: now,
+        }
+        .update(timing, now, window, detected)
+      },
+      GameState::Leaving {
+        since, ..
+      } => {
+        if elapsed(now, since) >= timing.exit_delay_ms {
+          GameState::Idle
+        } else {
+          GameState::Leaving {
+            window,
+            since,
+          }
+        }
+      },
+      GameState::Grace {
+        since,
+      } => {
+        if elapsed(now, since) >= timing.grace_period_ms {
+          GameState::Idle.update(timing, now, window, detected)
+        } else if detected {
+          // Back in a game before the grace period ran out
+          GameState::Active {
+            window,
+          }
+        } else {
+          self
+        }
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GAME: isize = 1;
+  const LAUNCHER: isize = 2;
+
+  fn timing(enter_delay_ms: u32, exit_delay_ms: u32, grace_period_ms: u32) -> GameModeTiming {
+    GameModeTiming {
+      enter_delay_ms,
+      exit_delay_ms,
+      grace_period_ms,
+    }
+  }
+
+  /// Runs detection results through the state machine, returning whether
+  /// game mode was on after each.
+  fn run(timing: GameModeTiming, samples: &[(u32, isize, bool)]) -> Vec<bool> {
+    let mut state = GameState::new();
+    samples
+      .iter()
+      .map(|&(now, window, detected)| {
+        state = state.update(timing, now, window, detected);
+        state.is_game()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn without_delays_detection_applies_at_once() {
+    let samples = [(0, GAME, true), (10, GAME, false), (20, LAUNCHER, true)];
+    assert_eq!(
+      run(GameModeTiming::default(), &samples),
+      [true, false, true]
+    );
+  }
+
+  #[test]
+  fn entering_waits_for_the_delay() {
+    let samples = [
+      (0, GAME, true),
+      (400, GAME, true),
+      (500, GAME, true),
+      (600, GAME, false),
+      (700, GAME, true),
+      (1100, GAME, true),
+      (1200, GAME, true),
+    ];
+    assert_eq!(
+      run(timing(500, 0, 0), &samples),
+      [false, false, true, false, false, false, true]
+    );
+  }
+
+  #[test]
+  fn leaving_waits_for_the_delay() {
+    let samples = [
+      (0, GAME, true),
+      (100, GAME, false),
+      (500, GAME, true),
+      (600, GAME, false),
+      (1599, GAME, false),
+      (1600, GAME, false),
+    ];
+    assert_eq!(
+      run(timing(0, 1000, 0), &samples),
+      [true, true, true, true, true, false]
+    );
+  }
+
+  #[test]
+  fn game_mode_sticks_after_switching_away() {
+    let samples = [
+      (0, GAME, true),
+      (100, LAUNCHER, false),
+      (2000, LAUNCHER, false),
+      (2500, GAME, true),
+      (2600, LAUNCHER, false),
+      (5600, LAUNCHER, false),
+    ];
+    assert_eq!(
+      run(timing(0, 0, 3000), &samples),
+      [true, true, true, true, true, false]
+    );
+  }
+
+  #[test]
+  fn pending_changes_have_a_deadline() {
+    let timing = timing(500, 1000, 3000);
+    let state = GameState::new().update(timing, 100, GAME, true);
+    assert_eq!(state.deadline(timing), Some(600));
+
+    let state = state.update(timing, 600, GAME, true);
+    assert_eq!(state.deadline(timing), None);
+    assert_eq!(
+      state.update(timing, 700, GAME, false).deadline(timing),
+      Some(1700)
+    );
+    assert_eq!(
+      state.update(timing, 700, LAUNCHER, false).deadline(timing),
+      Some(3700)
+    );
+  }
+
+  #[test]
+  fn timestamps_may_wrap() {
+    let start = u32::MAX - 100;
+    let samples = [
+      (start, GAME, true),
+      (start.wrapping_add(300), GAME, true),
+      (start.wrapping_add(600), GAME, true),
+    ];
+    assert_eq!(run(timing(500, 0, 0), &samples), [false, false, true]);
+  }
+}
@@ -0,0 +1,139 @@
+//! Games installed through the Epic Games Launcher, from the `.item` JSON
+//! manifest it keeps for every installed app in its `Manifests` directory.
+
+use std::{
+  env, fs,
+  path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+use super::{GameLibrary, registry_string};
+
+/// The part of a manifest we need.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+struct Manifest {
+  install_location: String,
+  /// Tells games apart from engines and other tools, which are
+  /// installed the same way
+  app_categories: Vec<String>,
+  /// Set while the launcher is still downloading or has given up
+  #[serde(rename = "bIsIncompleteInstall")]
+  incomplete: bool,
+}
+
+/// Install directory of the app described by a manifest. `None` for apps that
+/// aren't games or aren't fully installed.
+fn install_location(text: &str) -> Result<Option<String>, String> {
+  let manifest: Manifest = serde_json::from_str(text).map_err(|e| e.to_string())?;
+  if manifest.incomplete || !manifest.app_categories.iter().any(|c| c == "games") {
+    return Ok(None);
+  }
+
+  if manifest.install_location.is_empty() {
+    return Err("no \"InstallLocation\"".to_string());
+  }
+  Ok(Some(manifest.install_location))
+}
+
+/// Adds the games described by the manifests in `dir`.
+fn scan_manifests(dir: &Path, library: &mut GameLibrary) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+
+  for entry in entries.flatten() {
+    let path = entry.path();
+    if path.extension().is_none_or(|ext| ext != "item") {
+      continue;
+    }
+
+    let result = fs::read_to_string(&path)
+      .map_err(|e| e.to_string())
+      .and_then(|text| install_location(&text));
+    match result {
+      Ok(Some(dir)) => library.add(dir),
+      Ok(None) => {},
+      Err(e) => eprintln!("Skipping Epic Games manifest {}: {}", path.display(), e),
+    }
+  }
+}
+
+/// Where the launcher keeps its manifests. The launcher records its data
+/// directory in the registry, which is under `ProgramData` unless moved.
+fn manifests_dir() -> Option<PathBuf> {
+  let data = registry_string(
+    HKEY_LOCAL_MACHINE,
+    r"SOFTWARE\WOW6432Node\Epic Games\EpicGamesLauncher",
+    "AppDataPath",
+  )
+  .map(PathBuf::from)
+  .or_else(|| {
+    env::var_os("ProgramData").map(|dir| Path::new(&dir).join(r"Epic\EpicGamesLauncher\Data"))
+  })?;
+  Some(data.join("Manifests"))
+}
+
+/// Adds the games installed through the Epic Games Launcher, if it is
+/// installed.
+pub fn scan(library: &mut GameLibrary) {
+  if let Some(dir) = manifests_dir() {
+    scan_manifests(&dir, library);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::library::tests::TempDir;
+
+  const FORTNITE: &str =
+    include_str!("../../tests/fixtures/epic/4B0B1A8E8F5D4E2C9A1B3C5D7E9F1A2B.item");
+  const UNREAL_ENGINE: &str =
+    include_str!("../../tests/fixtures/epic/9E8D7C6B5A4F3E2D1C0B9A8F7E6D5C4B.item");
+  const INCOMPLETE: &str =
+    include_str!("../../tests/fixtures/epic/1F2E3D4C5B6A79881F2E3D4C5B6A7988.item");
+  const TRUNCATED: &str =
+    include_str!("../../tests/fixtures/epic/7A6B5C4D3E2F10097A6B5C4D3E2F1009.item");
+
+  #[test]
+  fn manifests_give_the_install_location() {
+    assert_eq!(
+      install_location(FORTNITE).unwrap().as_deref(),
+      Some(r"C:\Program Files\Epic Games\Fortnite")
+    );
+    assert_eq!(install_location(UNREAL_ENGINE).unwrap(), None);
+    assert_eq!(install_location(INCOMPLETE).unwrap(), None);
+    assert!(install_location(TRUNCATED).is_err());
+    assert!(install_location(r#"{ "AppCategories": ["games"] }"#).is_err());
+  }
+
+  #[test]
+  fn every_manifest_is_scanned() {
+    let dir = TempDir::new("epic");
+    let backup = FORTNITE.replace("Fortnite", "Hades");
+    let manifests = [
+      ("fortnite.item", FORTNITE),
+      ("unreal.item", UNREAL_ENGINE),
+      ("rocket_league.item", INCOMPLETE),
+      ("hades.item", TRUNCATED),
+      ("hades.item.bak", &backup),
+    ];
+    for (name, text) in manifests {
+      fs::write(dir.join(name), text).unwrap();
+    }
+
+    let mut library = GameLibrary::default();
+    scan_manifests(&dir, &mut library);
+
+    assert!(library.contains(r"C:\Program Files\Epic Games\Fortnite\FortniteGame\Fortnite.exe"));
+    assert!(!library.contains(r"C:\Program Files\Epic Games\Hades\Hades.exe"));
+    assert_eq!(library.len(), 1);
+
+    // A missing directory is the launcher not being installed
+    scan_manifests(&dir.join("Missing"), &mut library);
+    assert_eq!(library.len(), 1);
+  }
+}
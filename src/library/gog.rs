@@ -0,0 +1,121 @@
+//! Games installed through GOG Galaxy or GOG's offline installers. Both
+//! record every game's directory in the registry, and each directory has a
+//! `goggame-<id>.info` JSON file describing the game and its DLC.
+
+use std::{fs, path::Path};
+
+use serde::Deserialize;
+use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+
+use super::{GameLibrary, registry_string, registry_subkeys};
+
+const GAMES_SUBKEY: &str = r"SOFTWARE\WOW6432Node\GOG.com\Games";
+
+/// The part of a `goggame-*.info` file we need.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameInfo {
+  game_id: String,
+  /// The base game for DLC, which shares its directory
+  #[serde(default)]
+  root_game_id: Option<String>,
+}
+
+/// ID of the game described by an info file. `None` for DLC.
+fn game_id(text: &str) -> Result<Option<String>, String> {
+  let info: GameInfo = serde_json::from_str(text).map_err(|e| e.to_string())?;
+  match info.root_game_id {
+    Some(root) if root != info.game_id => Ok(None),
+    _ => Ok(Some(info.game_id)),
+  }
+}
+
+/// Adds the directory `dir` if it still holds a game. Uninstalling can leave
+/// the registry entry behind.
+fn scan_install_dir(dir: &Path, library: &mut GameLibrary) {
+  let Ok(entries) = fs::read_dir(dir) else {
+    return;
+  };
+
+  for entry in entries.flatten() {
+    let name = entry.file_name();
+    let name = name.to_string_lossy();
+    if !(name.starts_with("goggame-") && name.ends_with(".info")) {
+      continue;
+    }
+
+    let path = entry.path();
+    let result = fs::read_to_string(&path)
+      .map_err(|e| e.to_string())
+      .and_then(|text| game_id(&text));
+    match result {
+      Ok(Some(_)) => {
+        library.add(dir);
+        return;
+      },
+      Ok(None) => {},
+      Err(e) => eprintln!("Skipping GOG game info {}: {}", path.display(), e),
+    }
+  }
+}
+
+/// Adds the games installed from GOG, if there are any.
+pub fn scan(library: &mut GameLibrary) {
+  for id in registry_subkeys(HKEY_LOCAL_MACHINE, GAMES_SUBKEY) {
+    let subkey = format!(r"{}\{}", GAMES_SUBKEY, id);
+    if let Some(dir) = registry_string(HKEY_LOCAL_MACHINE, &subkey, "path") {
+      scan_install_dir(Path::new(&dir), library);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::library::tests::TempDir;
+
+  const WITCHER: &str = include_str!("../../tests/fixtures/gog/goggame-1207664663.info");
+  const HEARTS_OF_STONE: &str = include_str!("../../tests/fixtures/gog/goggame-1640424747.info");
+  const TRUNCATED: &str = include_str!("../../tests/fixtures/gog/goggame-1495134320.info");
+
+  #[test]
+  fn info_files_tell_games_from_dlc() {
+    assert_eq!(game_id(WITCHER).unwrap().as_deref(), Some("1207664663"));
+    assert_eq!(game_id(HEARTS_OF_STONE).unwrap(), None);
+    assert_eq!(
+      game_id(r#"{ "gameId": "1207664663" }"#).unwrap().as_deref(),
+      Some("1207664663")
+    );
+    assert!(game_id(TRUNCATED).is_err());
+    assert!(game_id(r#"{ "name": "Cyberpunk 2077" }"#).is_err());
+  }
+
+  #[test]
+  fn directories_need_a_game_in_them() {
+    let root = TempDir::new("gog");
+    let witcher = root.join("The Witcher 3 Wild Hunt GOTY");
+    let cyberpunk = root.join("Cyberpunk 2077");
+    let uninstalled = root.join("Uninstalled");
+    let files = [
+      (&witcher, "goggame-1207664663.info", WITCHER),
+      (&witcher, "goggame-1640424747.info", HEARTS_OF_STONE),
+      (&cyberpunk, "goggame-1495134320.info", TRUNCATED),
+      (&cyberpunk, "goggame-1495134320.info.bak", WITCHER),
+      (&uninstalled, "unins000.dat", ""),
+    ];
+    for (dir, name, text) in files {
+      fs::create_dir_all(dir).unwrap();
+      fs::write(dir.join(name), text).unwrap();
+    }
+
+    let mut library = GameLibrary::default();
+    for dir in [&witcher, &cyberpunk, &uninstalled, &root.join("Missing")] {
+      scan_install_dir(dir, &mut library);
+    }
+
+    let exe = |dir: &Path, path: &str| dir.join(path).display().to_string();
+    assert!(library.contains(&exe(&witcher, r"bin\x64_dx12\witcher3.exe")));
+    assert!(!library.contains(&exe(&cyberpunk, r"bin\x64\Cyberpunk2077.exe")));
+    assert_eq!(library.len(), 1);
+  }
+}
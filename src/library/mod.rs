@@ -0,0 +1,212 @@
+//! Games installed through launchers, found from the launchers' own records
+//! of what they installed where.
+
+use std::path::Path;
+
+use windows::{
+  Win32::{
+    Foundation::ERROR_SUCCESS,
+    System::Registry::{
+      HKEY, KEY_ENUMERATE_SUB_KEYS, RRF_RT_REG_SZ, RegCloseKey, RegEnumKeyExW, RegGetValueW,
+      RegOpenKeyExW,
+    },
+  },
+  core::PWSTR,
+};
+
+use crate::{rules::fold_path, wide_string::ToWide};
+
+mod epic;
+mod gog;
+mod steam;
+mod vdf;
+mod xbox;
+
+/// A launcher whose games we can find.
+struct Launcher {
+  name: &'static str,
+  /// Adds the launcher's games, doing nothing if it isn't installed. Broken
+  /// records are skipped with a message rather than failing the whole scan.
+  scan: fn(&mut GameLibrary),
+}
+
+const LAUNCHERS: &[Launcher] = &[
+  Launcher {
+    name: "Steam",
+    scan: steam::scan,
+  },
+  Launcher {
+    name: "Epic Games",
+    scan: epic::scan,
+  },
+  Launcher {
+    name: "GOG Galaxy",
+    scan: gog::scan,
+  },
+  Launcher {
+    name: "Xbox",
+    scan: xbox::scan,
+  },
+];
+
+/// Folds a path the way `GameLibrary` stores it.
+fn fold(path: &Path) -> String {
+  fold_path(&path.to_string_lossy()).collect()
+}
+
+/// Reads a string value from the registry.
+fn registry_string(key: HKEY, subkey: &str, value: &str) -> Option<String> {
+  let mut buffer = [0u16; 1024];
+  let mut len = (buffer.len() * 2) as u32;
+  let result = unsafe {
+    RegGetValueW(
+      key,
+      subkey.to_wide().as_pwstr(),
+      value.to_wide().as_pwstr(),
+      RRF_RT_REG_SZ,
+      None,
+      Some(buffer.as_mut_ptr() as _),
+      Some(&mut len),
+    )
+  };
+  // The length is in bytes and includes the terminating null
+  (result == ERROR_SUCCESS)
+    .then(|| String::from_utf16_lossy(&buffer[..(len as usize / 2).saturating_sub(1)]))
+}
+
+/// Names of the subkeys of a registry key, empty if it doesn't exist.
+fn registry_subkeys(key: HKEY, subkey: &str) -> Vec<String> {
+  let mut names = Vec::new();
+  let mut hkey = HKEY::default();
+  unsafe {
+    if RegOpenKeyExW(
+      key,
+      subkey.to_wide().as_pwstr(),
+      None,
+      KEY_ENUMERATE_SUB_KEYS,
+      &mut hkey,
+    ) != ERROR_SUCCESS
+    {
+      return names;
+    }
+
+    // Key names are at most 255 characters
+    let mut buffer = [0u16; 256];
+    for index in 0.. {
+      let mut len = buffer.len() as u32;
+      let result = RegEnumKeyExW(
+        hkey,
+        index,
+        Some(PWSTR(buffer.as_mut_ptr())),
+        &mut len,
+        None,
+        None,
+        None,
+        None,
+      );
+      if result != ERROR_SUCCESS {
+        break;
+      }
+      names.push(String::from_utf16_lossy(&buffer[..len as usize]));
+    }
+
+    let _ = RegCloseKey(hkey);
+  }
+  names
+}
+
+/// Install directories of known games.
+#[derive(Debug, Default)]
+pub struct GameLibrary {
+  /// Folded for comparison with process paths, each ending in a backslash
+  dirs: Vec<String>,
+}
+
+impl GameLibrary {
+  /// Adds a game's install directory.
+  pub fn add(&mut self, dir: impl AsRef<Path>) {
+    let mut folded = fold(dir.as_ref());
+    // Whole directories have to match, so `C:\Games\Foo` doesn't take in
+    // `C:\Games\Foobar\foobar.exe`
+    if !folded.ends_with('\\') {
+      folded.push('\\');
+    }
+    if !self.dirs.contains(&folded) {
+      self.dirs.push(folded);
+    }
+  }
+
+  /// Whether the executable at `path` lives in a game's install directory.
+  pub fn contains(&self, path: &str) -> bool {
+    let path: String = fold_path(path).collect();
+    self.dirs.iter().any(|dir| path.starts_with(dir.as_str()))
+  }
+
+  #[cfg(test)]
+  fn len(&self) -> usize {
+    self.dirs.len()
+  }
+}
+
+/// Finds the games installed through every launcher we know about.
+pub fn discover() -> GameLibrary {
+  let mut library = GameLibrary::default();
+  for launcher in LAUNCHERS {
+    let before = library.dirs.len();
+    (launcher.scan)(&mut library);
+    println!(
+      "Found {} games from {}",
+      library.dirs.len() - before,
+      launcher.name
+    );
+  }
+  library
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, fs, ops::Deref, path::PathBuf, process};
+
+  use super::*;
+
+  /// Directory for a test's files, removed when the test ends even if an
+  /// assertion failed.
+  pub(super) struct TempDir(PathBuf);
+
+  impl TempDir {
+    pub(super) fn new(name: &str) -> Self {
+      let dir = env::temp_dir().join(format!("winkeylock-{}-{}", name, process::id()));
+      fs::create_dir_all(&dir).unwrap();
+      Self(dir)
+    }
+  }
+
+  impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+      &self.0
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  #[test]
+  fn games_are_found_by_their_directory() {
+    let mut library = GameLibrary::default();
+    library.add(r"C:\Games\Foo");
+    library.add("d:/steamlibrary/steamapps/common/Bar/");
+
+    assert!(library.contains(r"C:\GAMES\foo\bin\foo.exe"));
+    assert!(library.contains(r"D:\SteamLibrary\steamapps\common\Bar\bar.exe"));
+    assert!(!library.contains(r"C:\Games\Foobar\foobar.exe"));
+    assert!(!library.contains(r"C:\Games\foo.exe"));
+
+    library.add(r"c:\games\foo\");
+    assert_eq!(library.len(), 2);
+  }
+}
@@ -0,0 +1,200 @@
+//! Games installed through Steam, from the library folders listed in
+//! `steamapps\libraryfolders.vdf` and the `appmanifest_*.acf` file Steam
+//! keeps in each library for every installed app.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use windows::Win32::System::Registry::HKEY_CURRENT_USER;
+
+use super::{GameLibrary, fold, registry_string, vdf};
+
+/// Steamworks Common Redistributables, which every Steam installation has
+/// and which only holds installers.
+const REDISTRIBUTABLES_APP_ID: &str = "228980";
+
+/// Library folders listed in `libraryfolders.vdf`. Older versions of Steam
+/// list bare paths, newer ones a block with a `path` in it.
+fn library_folders(text: &str) -> Result<Vec<PathBuf>, String> {
+  let value = vdf::parse(text)?;
+  let folders = value
+    .get("libraryfolders")
+    .ok_or("no \"libraryfolders\" block")?;
+
+  Ok(
+    folders
+      .entries()
+      .iter()
+      // Other entries hold statistics
+      .filter(|(key, _)| key.parse::<u32>().is_ok())
+      .filter_map(|(_, folder)| folder.as_str().or_else(|| folder.get_str("path")))
+      .map(PathBuf::from)
+      .collect(),
+  )
+}
+
+/// Name of the app's directory under `steamapps\common`, from its
+/// `appmanifest_*.acf`. `None` for apps that aren't games.
+fn install_dir(text: &str) -> Result<Option<String>, String> {
+  let value = vdf::parse(text)?;
+  let app = value.get("AppState").ok_or("no \"AppState\" block")?;
+  if app.get_str("appid") == Some(REDISTRIBUTABLES_APP_ID) {
+    return Ok(None);
+  }
+
+  match app.get_str("installdir") {
+    Some(dir) if !dir.is_empty() => Ok(Some(dir.to_string())),
+    _ => Err("no \"installdir\"".to_string()),
+  }
+}
+
+/// Adds the games in one library folder.
+fn scan_library_folder(folder: &Path, library: &mut GameLibrary) {
+  let steamapps = folder.join("steamapps");
+  // Libraries on drives that aren't connected are still listed
+  let Ok(entries) = fs::read_dir(&steamapps) else {
+    return;
+  };
+
+  for entry in entries.flatten() {
+    let name = entry.file_name();
+    let name = name.to_string_lossy();
+    if !(name.starts_with("appmanifest_") && name.ends_with(".acf")) {
+      continue;
+    }
+
+    let path = entry.path();
+    let result = fs::read_to_string(&path)
+      .map_err(|e| e.to_string())
+      .and_then(|text| install_dir(&text));
+    match result {
+      Ok(Some(dir)) => library.add(steamapps.join("common").join(dir)),
+      Ok(None) => {},
+      Err(e) => eprintln!("Skipping Steam manifest {}: {}", path.display(), e),
+    }
+  }
+}
+
+/// Adds the games in every library of the Steam installation at `steam`.
+fn scan_steam_dir(steam: &Path, library: &mut GameLibrary) {
+  // The library in Steam's own directory isn't listed by older versions
+  let mut folders = vec![steam.to_path_buf()];
+
+  let list = steam.join("steamapps").join("libraryfolders.vdf");
+  if let Ok(text) = fs::read_to_string(&list) {
+    match library_folders(&text) {
+      Ok(listed) => folders.extend(listed),
+      Err(e) => {
+        eprintln!(
+          "Failed to read Steam libraries from {}: {}",
+          list.display(),
+          e
+        )
+      },
+    }
+  }
+
+  let mut scanned = Vec::new();
+  for folder in folders {
+    let folded = fold(&folder);
+    if !scanned.contains(&folded) {
+      scan_library_folder(&folder, library);
+      scanned.push(folded);
+    }
+  }
+}
+
+/// Adds the games installed through Steam, if it is installed.
+pub fn scan(library: &mut GameLibrary) {
+  if let Some(steam) = registry_string(HKEY_CURRENT_USER, r"Software\Valve\Steam", "SteamPath") {
+    scan_steam_dir(Path::new(&steam), library);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::library::tests::TempDir;
+
+  const LIBRARY_FOLDERS: &str = include_str!("../../tests/fixtures/steam/libraryfolders.vdf");
+  const LEGACY_LIBRARY_FOLDERS: &str =
+    include_str!("../../tests/fixtures/steam/libraryfolders_legacy.vdf");
+  const DOTA: &str = include_str!("../../tests/fixtures/steam/appmanifest_570.acf");
+  const ELDEN_RING: &str = include_str!("../../tests/fixtures/steam/appmanifest_1245620.acf");
+  const REDISTRIBUTABLES: &str = include_str!("../../tests/fixtures/steam/appmanifest_228980.acf");
+  const TRUNCATED: &str = include_str!("../../tests/fixtures/steam/appmanifest_1091500.acf");
+
+  #[test]
+  fn library_folders_are_listed_in_either_format() {
+    assert_eq!(
+      library_folders(LIBRARY_FOLDERS).unwrap(),
+      [
+        PathBuf::from(r"C:\Program Files (x86)\Steam"),
+        PathBuf::from(r"D:\SteamLibrary"),
+      ]
+    );
+    assert_eq!(
+      library_folders(LEGACY_LIBRARY_FOLDERS).unwrap(),
+      [
+        PathBuf::from(r"D:\SteamLibrary"),
+        PathBuf::from(r"E:\Games\Steam"),
+      ]
+    );
+    assert!(library_folders(DOTA).is_err());
+  }
+
+  #[test]
+  fn manifests_give_the_install_dir() {
+    assert_eq!(install_dir(DOTA).unwrap().as_deref(), Some("dota 2 beta"));
+    assert_eq!(
+      install_dir(ELDEN_RING).unwrap().as_deref(),
+      Some("ELDEN RING")
+    );
+    assert_eq!(install_dir(REDISTRIBUTABLES).unwrap(), None);
+    assert!(install_dir(TRUNCATED).is_err());
+  }
+
+  #[test]
+  fn every_library_is_scanned() {
+    let root = TempDir::new("steam");
+    let steam = root.join("Steam");
+    let games = root.join("SteamLibrary");
+    let manifests = [
+      (&steam, "appmanifest_570.acf", DOTA),
+      (&steam, "appmanifest_228980.acf", REDISTRIBUTABLES),
+      (&games, "appmanifest_1245620.acf", ELDEN_RING),
+      (&games, "appmanifest_1091500.acf", TRUNCATED),
+      (&games, "appmanifest_1091500.acf.tmp", DOTA),
+    ];
+    for (folder, name, text) in manifests {
+      fs::create_dir_all(folder.join("steamapps")).unwrap();
+      fs::write(folder.join("steamapps").join(name), text).unwrap();
+    }
+    let escape = |path: &Path| path.display().to_string().replace('\\', r"\\");
+    let list = format!(
+      r#""libraryfolders" {{ "0" {{ "path" "{}" }} "1" {{ "path" "{}" }} "2" {{ "path" "{}" }} }}"#,
+      escape(&steam),
+      escape(&games),
+      escape(&root.join("Disconnected")),
+    );
+    fs::write(steam.join("steamapps").join("libraryfolders.vdf"), list).unwrap();
+
+    let mut library = GameLibrary::default();
+    scan_steam_dir(&steam, &mut library);
+
+    let common = |folder: &Path, path: &str| {
+      folder
+        .join("steamapps")
+        .join("common")
+        .join(path)
+        .display()
+        .to_string()
+    };
+    assert!(library.contains(&common(&steam, "dota 2 beta/game/bin/win64/dota2.exe")));
+    assert!(library.contains(&common(&games, "ELDEN RING/Game/eldenring.exe")));
+    assert!(!library.contains(&common(&steam, "Steamworks Shared/vcredist_x64.exe")));
+    assert_eq!(library.len(), 2);
+  }
+}
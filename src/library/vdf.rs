@@ -0,0 +1,234 @@
+//! Reader for Valve's KeyValues text format, used by Steam's `.vdf` and
+//! `.acf` files:
+//!
+//! ```text
+//! "AppState"
+//! {
+//!   "appid"      "570"
+//!   "installdir" "dota 2 beta"
+//! }
+//! ```
+
+use std::{iter::Peekable, str::Chars};
+
+/// A value: either a string or a block of keyed values, in file order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+  String(String),
+  Block(Vec<(String, Value)>),
+}
+
+impl Value {
+  /// The first value under `key` in a block. Keys are compared ignoring
+  /// ASCII case, as Steam itself does.
+  pub fn get(&self, key: &str) -> Option<&Value> {
+    self
+      .entries()
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(key))
+      .map(|(_, value)| value)
+  }
+
+  /// The string under `key` in a block.
+  pub fn get_str(&self, key: &str) -> Option<&str> {
+    self.get(key).and_then(Value::as_str)
+  }
+
+  pub fn as_str(&self) -> Option<&str> {
+    match self {
+      Value::String(s) => Some(s),
+      Value::Block(_) => None,
+    }
+  }
+
+  /// The keyed values of a block, or nothing for a string.
+  pub fn entries(&self) -> &[(String, Value)] {
+    match self {
+      Value::String(_) => &[],
+      Value::Block(entries) => entries,
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+  String(String),
+  Open,
+  Close,
+}
+
+struct Lexer<'a> {
+  chars: Peekable<Chars<'a>>,
+  line: usize,
+}
+
+impl<'a> Lexer<'a> {
+  fn new(text: &'a str) -> Self {
+    Self {
+      chars: text.chars().peekable(),
+      line: 1,
+    }
+  }
+
+  fn error(&self, message: &str) -> String {
+    format!("line {}: {}", self.line, message)
+  }
+
+  fn skip_whitespace_and_comments(&mut self) {
+    while let Some(&c) = self.chars.peek() {
+      if c == '\n' {
+        self.line += 1;
+        self.chars.next();
+      } else if c.is_whitespace() || c == '\u{feff}' {
+        self.chars.next();
+      } else if c == '/' {
+        // Comments run from `//` to the end of the line
+        let mut rest = self.chars.clone();
+        rest.next();
+        if rest.peek() != Some(&'/') {
+          return;
+        }
+        while self.chars.peek().is_some_and(|&c| c != '\n') {
+          self.chars.next();
+        }
+      } else if c == '[' {
+        // Platform conditions like `[$WIN32]` after a value; we're always on
+        // Windows, so they're ignored
+        while self.chars.next().is_some_and(|c| c != ']') {}
+      } else {
+        return;
+      }
+    }
+  }
+
+  fn next_token(&mut self) -> Result<Option<Token>, String> {
+    self.skip_whitespace_and_comments();
+    let Some(c) = self.chars.next() else {
+      return Ok(None);
+    };
+
+    match c {
+      '{' => Ok(Some(Token::Open)),
+      '}' => Ok(Some(Token::Close)),
+      '"' => {
+        let mut s = String::new();
+        loop {
+          match self.chars.next() {
+            Some('"') => return Ok(Some(Token::String(s))),
+            Some('\\') => {
+              match self.chars.next() {
+                Some('n') => s.push('\n'),
+                Some('t') => s.push('\t'),
+                // `\\` and `\"`, and anything else stands for itself
+                Some(c) => s.push(c),
+                None => break,
+              }
+            },
+            Some(c) => {
+              if c == '\n' {
+                self.line += 1;
+              }
+              s.push(c);
+            },
+            None => break,
+          }
+        }
+        Err(self.error("unterminated string"))
+      },
+      c => {
+        // Unquoted strings end at whitespace or a brace
+        let mut s = String::from(c);
+        while let Some(&c) = self.chars.peek() {
+          if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+            break;
+          }
+          s.push(c);
+          self.chars.next();
+        }
+        Ok(Some(Token::String(s)))
+      },
+    }
+  }
+}
+
+/// Reads the entries of a block up to its closing brace, or to the end of
+/// the text for the top level.
+fn parse_entries(lexer: &mut Lexer, top_level: bool) -> Result<Vec<(String, Value)>, String> {
+  let mut entries = Vec::new();
+  loop {
+    let key = match lexer.next_token()? {
+      Some(Token::String(key)) => key,
+      Some(Token::Close) if !top_level => return Ok(entries),
+      None if top_level => return Ok(entries),
+      Some(Token::Close) => return Err(lexer.error("unexpected `}`")),
+      Some(Token::Open) => return Err(lexer.error("expected a key, found `{`")),
+      None => return Err(lexer.error("missing `}` at end of file")),
+    };
+
+    let value = match lexer.next_token()? {
+      Some(Token::String(value)) => Value::String(value),
+      Some(Token::Open) => Value::Block(parse_entries(lexer, false)?),
+      Some(Token::Close) | None => {
+        return Err(lexer.error(&format!("missing value for \"{}\"", key)));
+      },
+    };
+    entries.push((key, value));
+  }
+}
+
+/// Parses a whole file, which is a block without the braces. Steam's files
+/// have a single entry at the top, such as `"AppState" { ... }`.
+pub fn parse(text: &str) -> Result<Value, String> {
+  parse_entries(&mut Lexer::new(text), true).map(Value::Block)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn blocks_nest_and_keys_ignore_case() {
+    let value = parse(
+      r#"
+      // A comment
+      "AppState"
+      {
+        "appid"   "570"
+        "UserConfig"
+        {
+          "language" "english"
+        }
+      }
+      "#,
+    )
+    .unwrap();
+
+    let app = value.get("appstate").unwrap();
+    assert_eq!(app.get_str("AppID"), Some("570"));
+    assert_eq!(
+      app.get("userconfig").unwrap().get_str("language"),
+      Some("english")
+    );
+    assert_eq!(app.get_str("UserConfig"), None);
+  }
+
+  #[test]
+  fn strings_can_be_escaped_or_unquoted() {
+    let value = parse(r#"root { path "C:\\Games\\\"Steam\"" size 42 [$WIN32] }"#).unwrap();
+    let root = value.get("root").unwrap();
+    assert_eq!(root.get_str("path"), Some(r#"C:\Games\"Steam""#));
+    assert_eq!(root.get_str("size"), Some("42"));
+  }
+
+  #[test]
+  fn broken_files_are_errors() {
+    assert!(parse(r#""AppState" { "appid" "570""#).is_err());
+    assert!(parse(r#""AppState" { "appid" }"#).is_err());
+    assert!(parse(r#""AppState" { "name" "unterminated }"#).is_err());
+    assert!(parse("}").is_err());
+    assert_eq!(
+      parse("\n\n\"AppState\" {").unwrap_err(),
+      "line 3: missing `}` at end of file"
+    );
+  }
+}
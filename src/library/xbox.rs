@@ -0,0 +1,126 @@
+//! Games installed through the Xbox app (PC Game Pass). Each drive the app
+//! installs to has a `.GamingRoot` file at its root naming the folders games
+//! go in, and each game's folder has a `Content\MicrosoftGame.config`.
+
+use std::{fs, path::Path};
+
+use windows::Win32::{
+  Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives},
+  System::WindowsProgramming::DRIVE_FIXED,
+};
+
+use super::GameLibrary;
+use crate::wide_string::ToWide;
+
+const MAGIC: &[u8] = b"RGBX";
+
+/// Folders listed in a `.GamingRoot` file, relative to the drive's root: the
+/// magic, a little-endian count, then that many null-terminated UTF-16
+/// strings. Complete entries are kept if the file ends early.
+fn gaming_root_dirs(bytes: &[u8]) -> Result<Vec<String>, String> {
+  let rest = bytes.strip_prefix(MAGIC).ok_or("not a .GamingRoot file")?;
+  let (count, rest) = rest.split_first_chunk::<4>().ok_or("no folder count")?;
+  let count = u32::from_le_bytes(*count) as usize;
+
+  let units: Vec<u16> = rest
+    .chunks_exact(2)
+    .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+    .collect();
+  let mut dirs: Vec<String> = units
+    .split(|&unit| unit == 0)
+    .map(String::from_utf16_lossy)
+    .collect();
+  // The last piece is whatever follows the last null, complete or not
+  dirs.pop();
+  dirs.truncate(count);
+  Ok(dirs)
+}
+
+/// Adds the games on the drive whose root is `root`.
+fn scan_drive(root: &Path, library: &mut GameLibrary) {
+  let file = root.join(".GamingRoot");
+  let Ok(bytes) = fs::read(&file) else {
+    return;
+  };
+  let dirs = match gaming_root_dirs(&bytes) {
+    Ok(dirs) => dirs,
+    Err(e) => {
+      eprintln!("Skipping Xbox games in {}: {}", file.display(), e);
+      return;
+    },
+  };
+
+  for dir in dirs {
+    let Ok(entries) = fs::read_dir(root.join(dir)) else {
+      continue;
+    };
+    for entry in entries.flatten() {
+      let game = entry.path();
+      // Leftovers of cancelled installs don't have a config
+      if game.join("Content").join("MicrosoftGame.config").is_file() {
+        library.add(game);
+      }
+    }
+  }
+}
+
+/// Adds the games installed through the Xbox app, on any local hard drive.
+/// Other drives are skipped, since an empty card reader or a disconnected
+/// network share can stall the scan.
+pub fn scan(library: &mut GameLibrary) {
+  let drives = unsafe { GetLogicalDrives() };
+  for (index, letter) in ('A'..='Z').enumerate() {
+    if drives & (1 << index) == 0 {
+      continue;
+    }
+    let root = format!(r"{}:\", letter);
+    if unsafe { GetDriveTypeW(root.to_wide().as_pwstr()) } != DRIVE_FIXED {
+      continue;
+    }
+    scan_drive(Path::new(&root), library);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::library::tests::TempDir;
+
+  const GAMING_ROOT: &[u8] = include_bytes!("../../tests/fixtures/xbox/GamingRoot");
+  const TRUNCATED: &[u8] = include_bytes!("../../tests/fixtures/xbox/GamingRoot_truncated");
+  const GAME_CONFIG: &str = include_str!("../../tests/fixtures/xbox/MicrosoftGame.config");
+
+  #[test]
+  fn gaming_roots_list_folders() {
+    assert_eq!(gaming_root_dirs(GAMING_ROOT).unwrap(), ["XboxGames"]);
+    assert_eq!(gaming_root_dirs(TRUNCATED).unwrap(), ["XboxGames"]);
+    assert!(gaming_root_dirs(b"RGBX").is_err());
+    assert!(gaming_root_dirs(b"<?xml version=\"1.0\"?>").is_err());
+  }
+
+  #[test]
+  fn games_need_a_config() {
+    let root = TempDir::new("xbox");
+    let games = root.join("XboxGames");
+    let forza = games.join("Forza Horizon 5");
+    fs::create_dir_all(forza.join("Content")).unwrap();
+    fs::write(
+      forza.join("Content").join("MicrosoftGame.config"),
+      GAME_CONFIG,
+    )
+    .unwrap();
+    fs::create_dir_all(games.join("Cancelled").join("Content")).unwrap();
+    fs::write(games.join("GameSave"), "").unwrap();
+    fs::write(root.join(".GamingRoot"), TRUNCATED).unwrap();
+
+    let mut library = GameLibrary::default();
+    scan_drive(&root, &mut library);
+    fs::write(root.join(".GamingRoot"), "garbage").unwrap();
+    scan_drive(&root, &mut library);
+
+    let exe = |dir: &Path, path: &str| dir.join(path).display().to_string();
+    assert!(library.contains(&exe(&forza, r"Content\ForzaHorizon5.exe")));
+    assert!(!library.contains(&exe(&games, r"Cancelled\Content\game.exe")));
+    assert_eq!(library.len(), 1);
+  }
+}
@@ -24,10 +24,19 @@ use windows_strings::w;
 
 use wide_string::ToWide;
 
+mod activity;
 mod autostart;
 mod config;
 mod config_ui;
+mod detect;
 mod disable_key;
+mod foreground;
+mod game_state;
+mod library;
+mod modifiers;
+mod presses;
+mod process;
+mod rules;
 mod wide_string;
 
 const APP_NAME: &str = "winkeylock";
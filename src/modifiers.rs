@@ -0,0 +1,267 @@
+use std::ops::{BitOr, BitOrAssign};
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+  VK_CONTROL, VK_LCONTROL, VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MENU, VK_RCONTROL, VK_RMENU, VK_RSHIFT,
+  VK_RWIN, VK_SHIFT,
+};
+
+/// Set of physical modifier keys that are held down, tracked per side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+  pub const ALT: Self = Self(Self::LALT.0 | Self::RALT.0);
+  pub const CTRL: Self = Self(Self::LCTRL.0 | Self::RCTRL.0);
+  pub const LALT: Self = Self(1 << 4);
+  pub const LCTRL: Self = Self(1 << 2);
+  pub const LSHIFT: Self = Self(1 << 0);
+  pub const LWIN: Self = Self(1 << 6);
+  pub const NONE: Self = Self(0);
+  pub const RALT: Self = Self(1 << 5);
+  pub const RCTRL: Self = Self(1 << 3);
+  pub const RSHIFT: Self = Self(1 << 1);
+  pub const RWIN: Self = Self(1 << 7);
+  pub const SHIFT: Self = Self(Self::LSHIFT.0 | Self::RSHIFT.0);
+
+  /// Returns true if every key in `other` is held.
+  pub const fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+
+  /// Returns true if any key in `other` is held.
+  pub const fn intersects(self, other: Self) -> bool {
+    self.0 & other.0 != 0
+  }
+
+  pub fn set(&mut self, other: Self, down: bool) {
+    if down {
+      self.0 |= other.0;
+    } else {
+      self.0 &= !other.0;
+    }
+  }
+
+  /// Maps a modifier virtual-key code to its flag. The low-level hook reports
+  /// side-specific codes, but the generic ones are folded onto the left side.
+  pub fn from_vk(vk: u16) -> Option<Self> {
+    match vk {
+      v if v == VK_LSHIFT.0 || v == VK_SHIFT.0 => Some(Self::LSHIFT),
+      v if v == VK_RSHIFT.0 => Some(Self::RSHIFT),
+      v if v == VK_LCONTROL.0 || v == VK_CONTROL.0 => Some(Self::LCTRL),
+      v if v == VK_RCONTROL.0 => Some(Self::RCTRL),
+      v if v == VK_LMENU.0 || v == VK_MENU.0 => Some(Self::LALT),
+      v if v == VK_RMENU.0 => Some(Self::RALT),
+      v if v == VK_LWIN.0 => Some(Self::LWIN),
+      v if v == VK_RWIN.0 => Some(Self::RWIN),
+      _ => None,
+    }
+  }
+}
+
+impl BitOr for Modifiers {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for Modifiers {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0;
+  }
+}
+
+/// Set of modifier states, one bit for each of the 256 combinations of held
+/// keys, so a rule's modifier requirements can be checked with a single lookup.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModifierSet([u64; 4]);
+
+impl ModifierSet {
+  pub const EMPTY: Self = Self([0; 4]);
+
+  /// Builds the set of every state for which `matches` returns true.
+  pub fn from_fn(matches: impl Fn(Modifiers) -> bool) -> Self {
+    let mut set = Self::EMPTY;
+    for bits in 0..=u8::MAX {
+      if matches(Modifiers(bits)) {
+        set.0[(bits >> 6) as usize] |= 1 << (bits & 63);
+      }
+    }
+    set
+  }
+
+  pub const fn contains(self, mods: Modifiers) -> bool {
+    self.0[(mods.0 >> 6) as usize] & (1 << (mods.0 & 63)) != 0
+  }
+}
+
+/// Side-specific virtual-key codes for every tracked modifier.
+const MODIFIER_KEYS: [u16; 8] = [
+  VK_LSHIFT.0,
+  VK_RSHIFT.0,
+  VK_LCONTROL.0,
+  VK_RCONTROL.0,
+  VK_LMENU.0,
+  VK_RMENU.0,
+  VK_LWIN.0,
+  VK_RWIN.0,
+];
+
+/// Tracks which modifier keys are physically held, one flag per key, so that
+/// releasing one side never clears the other.
+///
+/// Key-up events can be lost (for example when focus moves to the lock screen
+/// or an elevated window), so the tracker can be resynced from the real
+/// keyboard state whenever the caller suspects it has drifted.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ModifierTracker {
+  held: Modifiers,
+}
+
+impl ModifierTracker {
+  pub const fn new() -> Self {
+    Self {
+      held: Modifiers::NONE,
+    }
+  }
+
+  /// Applies a key event and returns the resulting state. Events for keys
+  /// that are not modifiers leave the state untouched.
+  pub fn update(&mut self, vk: u16, down: bool) -> Modifiers {
+    if let Some(flag) = Modifiers::from_vk(vk) {
+      self.held.set(flag, down);
+    }
+    self.held
+  }
+
+  /// Rebuilds the state from `is_down`, which reports whether a side-specific
+  /// modifier virtual-key code is currently held. The keyboard state alone
+  /// misses presses the hook swallowed, so callers combine it with those.
+  pub fn resync(&mut self, is_down: impl Fn(u16) -> bool) -> Modifiers {
+    let mut held = Modifiers::NONE;
+    for vk in MODIFIER_KEYS {
+      if is_down(vk) {
+        // Every entry in MODIFIER_KEYS maps to a flag
+        held |= Modifiers::from_vk(vk).unwrap_or_default();
+      }
+    }
+    self.held = held;
+    held
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use windows::Win32::UI::Input::KeyboardAndMouse::VK_A;
+
+  use super::*;
+  use crate::presses::PressTracker;
+
+  #[test]
+  fn overlapping_shift_presses_keep_the_other_side_held() {
+    let mut tracker = ModifierTracker::new();
+
+    tracker.update(VK_LSHIFT.0, true);
+    tracker.update(VK_RSHIFT.0, true);
+    assert_eq!(tracker.held, Modifiers::SHIFT);
+
+    let mods = tracker.update(VK_LSHIFT.0, false);
+    assert_eq!(mods, Modifiers::RSHIFT);
+    assert!(mods.intersects(Modifiers::SHIFT));
+
+    let mods = tracker.update(VK_RSHIFT.0, false);
+    assert_eq!(mods, Modifiers::NONE);
+  }
+
+  #[test]
+  fn overlapping_presses_across_groups() {
+    let mut tracker = ModifierTracker::new();
+
+    tracker.update(VK_LCONTROL.0, true);
+    tracker.update(VK_RMENU.0, true);
+    tracker.update(VK_LWIN.0, true);
+    tracker.update(VK_LCONTROL.0, false);
+
+    assert_eq!(tracker.held, Modifiers::RALT | Modifiers::LWIN);
+  }
+
+  #[test]
+  fn auto_repeat_and_stray_key_up_are_idempotent() {
+    let mut tracker = ModifierTracker::new();
+
+    // Held keys repeat their key-down
+    tracker.update(VK_LMENU.0, true);
+    tracker.update(VK_LMENU.0, true);
+    assert_eq!(tracker.held, Modifiers::LALT);
+
+    // A key-up for a key we never saw go down changes nothing
+    tracker.update(VK_RMENU.0, false);
+    assert_eq!(tracker.held, Modifiers::LALT);
+  }
+
+  #[test]
+  fn non_modifier_keys_are_ignored() {
+    let mut tracker = ModifierTracker::new();
+
+    tracker.update(VK_LSHIFT.0, true);
+    tracker.update(VK_A.0, true);
+    tracker.update(VK_A.0, false);
+
+    assert_eq!(tracker.held, Modifiers::LSHIFT);
+  }
+
+  #[test]
+  fn generic_codes_fold_onto_the_left_side() {
+    let mut tracker = ModifierTracker::new();
+
+    tracker.update(VK_SHIFT.0, true);
+    tracker.update(VK_CONTROL.0, true);
+    tracker.update(VK_MENU.0, true);
+
+    assert_eq!(
+      tracker.held,
+      Modifiers::LSHIFT | Modifiers::LCTRL | Modifiers::LALT
+    );
+  }
+
+  #[test]
+  fn resync_clears_stuck_modifiers() {
+    let mut tracker = ModifierTracker::new();
+
+    // The key-up for Left Win was swallowed by the lock screen
+    tracker.update(VK_LWIN.0, true);
+    tracker.update(VK_RSHIFT.0, true);
+
+    let mods = tracker.resync(|vk| vk == VK_RSHIFT.0);
+    assert_eq!(mods, Modifiers::RSHIFT);
+    assert_eq!(tracker.held, Modifiers::RSHIFT);
+  }
+
+  #[test]
+  fn resync_keeps_swallowed_modifiers() {
+    let mut tracker = ModifierTracker::new();
+    let mut presses = PressTracker::new();
+
+    // Left Win was blocked, so the keyboard state reports it as up
+    tracker.update(VK_LWIN.0, true);
+    presses.press(VK_LWIN.0, true);
+    tracker.update(VK_LSHIFT.0, true);
+    presses.press(VK_LSHIFT.0, false);
+
+    let mods = tracker.resync(|vk| presses.is_swallowed(vk));
+    assert_eq!(mods, Modifiers::LWIN);
+  }
+
+  #[test]
+  fn resync_picks_up_keys_pressed_while_untracked() {
+    let mut tracker = ModifierTracker::new();
+
+    let mods = tracker.resync(|vk| vk == VK_RCONTROL.0 || vk == VK_LSHIFT.0);
+    assert_eq!(mods, Modifiers::RCTRL | Modifiers::LSHIFT);
+
+    // Tracking continues normally afterwards
+    tracker.update(VK_RCONTROL.0, false);
+    assert_eq!(tracker.held, Modifiers::LSHIFT);
+  }
+}
@@ -0,0 +1,150 @@
+/// Fixed-size set of virtual-key codes.
+#[derive(Debug, Default, Clone, Copy)]
+struct KeySet([u64; 4]);
+
+impl KeySet {
+  const fn new() -> Self {
+    Self([0; 4])
+  }
+
+  fn contains(&self, vk: u16) -> bool {
+    let vk = vk as u8;
+    self.0[(vk >> 6) as usize] & (1 << (vk & 63)) != 0
+  }
+
+  fn set(&mut self, vk: u16, present: bool) {
+    let vk = vk as u8;
+    let word = &mut self.0[(vk >> 6) as usize];
+    if present {
+      *word |= 1 << (vk & 63);
+    } else {
+      *word &= !(1 << (vk & 63));
+    }
+  }
+
+  fn retain(&mut self, keep: impl Fn(u16) -> bool) {
+    for vk in 0..=255u16 {
+      if self.contains(vk) && !keep(vk) {
+        self.set(vk, false);
+      }
+    }
+  }
+}
+
+/// Remembers how each held key was handled when it went down, so that its
+/// auto-repeats and its key-up are treated the same way. Without this a
+/// blocked press would still leak a lone key-up (which is what opens the Start
+/// menu for the Windows key) and repeats would be judged one by one.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PressTracker {
+  down: KeySet,
+  swallowed: KeySet,
+}
+
+impl PressTracker {
+  pub const fn new() -> Self {
+    Self {
+      down: KeySet::new(),
+      swallowed: KeySet::new(),
+    }
+  }
+
+  /// Returns whether an auto-repeat of a held key should be swallowed, or
+  /// `None` if this key-down starts a new press.
+  pub fn repeat(&self, vk: u16) -> Option<bool> {
+    if self.down.contains(vk) {
+      Some(self.swallowed.contains(vk))
+    } else {
+      None
+    }
+  }
+
+  /// Records the start of a new press and whether it was swallowed.
+  pub fn press(&mut self, vk: u16, swallowed: bool) {
+    self.down.set(vk, true);
+    self.swallowed.set(vk, swallowed);
+  }
+
+  /// Ends a press and returns whether its key-up should be swallowed. This
+  /// only depends on how the key-down was handled, so a press keeps being
+  /// swallowed even if blocking turns off while the key is held.
+  pub fn release(&mut self, vk: u16) -> bool {
+    let swallowed = self.swallowed.contains(vk);
+    self.down.set(vk, false);
+    self.swallowed.set(vk, false);
+    swallowed
+  }
+
+  /// Whether `vk` is held down and its press was swallowed.
+  pub fn is_swallowed(&self, vk: u16) -> bool {
+    self.swallowed.contains(vk)
+  }
+
+  /// Drops every passed-through press whose key `is_down` reports as no
+  /// longer held, for when key-ups may have been delivered elsewhere.
+  /// Swallowed presses are kept: the system never saw them go down, so
+  /// `is_down` can't tell whether they are still held, and their key-up still
+  /// has to be swallowed.
+  pub fn forget_released(&mut self, is_down: impl Fn(u16) -> bool) {
+    let swallowed = self.swallowed;
+    self.down.retain(|vk| swallowed.contains(vk) || is_down(vk));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const LWIN: u16 = 0x5B;
+  const A: u16 = 0x41;
+
+  #[test]
+  fn repeats_follow_the_first_key_down() {
+    let mut presses = PressTracker::new();
+    assert_eq!(presses.repeat(LWIN), None);
+
+    presses.press(LWIN, true);
+    presses.press(A, false);
+    assert_eq!(presses.repeat(LWIN), Some(true));
+    assert_eq!(presses.repeat(A), Some(false));
+    assert!(presses.is_swallowed(LWIN));
+    assert!(!presses.is_swallowed(A));
+  }
+
+  #[test]
+  fn releases_end_the_press() {
+    let mut presses = PressTracker::new();
+    presses.press(LWIN, true);
+    presses.press(A, false);
+
+    assert!(presses.release(LWIN));
+    assert!(!presses.release(A));
+    assert_eq!(presses.repeat(LWIN), None);
+    assert!(!presses.is_swallowed(LWIN));
+
+    // A key-up for a key we never saw go down passes through
+    assert!(!presses.release(LWIN));
+  }
+
+  #[test]
+  fn resync_keeps_swallowed_presses() {
+    let mut presses = PressTracker::new();
+    presses.press(LWIN, true);
+    presses.press(A, false);
+
+    // Swallowed keys never reach the system, so it reports them as up
+    presses.forget_released(|_| false);
+    assert_eq!(presses.repeat(LWIN), Some(true));
+    assert_eq!(presses.repeat(A), None);
+    assert!(presses.release(LWIN));
+  }
+
+  #[test]
+  fn resync_keeps_passed_presses_that_are_still_held() {
+    let mut presses = PressTracker::new();
+    presses.press(A, false);
+
+    presses.forget_released(|vk| vk == A);
+    assert_eq!(presses.repeat(A), Some(false));
+  }
+}
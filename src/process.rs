@@ -0,0 +1,222 @@
+use std::{collections::HashMap, mem, ptr, slice};
+
+use windows::{
+  Wdk::System::Threading::{
+    NtQueryInformationProcess, ProcessBasicInformation, ProcessCommandLineInformation,
+  },
+  Win32::{
+    Foundation::{CloseHandle, ERROR_SUCCESS, FILETIME, HANDLE, UNICODE_STRING},
+    Storage::Packaging::Appx::GetPackageFamilyName,
+    System::Threading::{
+      GetProcessTimes, OpenProcess, PROCESS_BASIC_INFORMATION, PROCESS_NAME_WIN32,
+      PROCESS_QUERY_LIMITED_INFORMATION, QueryFullProcessImageNameW,
+    },
+  },
+  core::PWSTR,
+};
+
+use crate::config::WindowInfo;
+
+const PATH_LEN: usize = 1024;
+const PACKAGE_LEN: usize = 128;
+
+/// How far up the process tree window matchers can look.
+const MAX_ANCESTORS: usize = 16;
+
+/// Processes are forgotten all at once past this many, which only matters for
+/// long sessions that see a lot of short-lived windows.
+const MAX_CACHED: usize = 256;
+
+/// What window matchers need to know about a process.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessInfo {
+  /// When the process started, which tells it apart from a later process that
+  /// reuses its ID
+  pub created: u64,
+  /// ID of the process that started this one, which may have exited since
+  pub parent: u32,
+  pub path: String,
+  pub package: String,
+  pub command_line: String,
+}
+
+/// An open handle to a process, closed on drop.
+struct Process(HANDLE);
+
+impl Process {
+  fn open(pid: u32) -> Option<Self> {
+    unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }
+      .ok()
+      .map(Process)
+  }
+
+  fn created(&self) -> Option<u64> {
+    let mut created = FILETIME::default();
+    let mut unused = FILETIME::default();
+    unsafe { GetProcessTimes(self.0, &mut created, &mut unused, &mut unused, &mut unused) }.ok()?;
+    Some((created.dwHighDateTime as u64) << 32 | created.dwLowDateTime as u64)
+  }
+
+  fn parent(&self) -> u32 {
+    let mut info: PROCESS_BASIC_INFORMATION = unsafe { mem::zeroed() };
+    let status = unsafe {
+      NtQueryInformationProcess(
+        self.0,
+        ProcessBasicInformation,
+        &mut info as *mut _ as _,
+        mem::size_of::<PROCESS_BASIC_INFORMATION>() as u32,
+        ptr::null_mut(),
+      )
+    };
+    if status.is_ok() {
+      info.InheritedFromUniqueProcessId as u32
+    } else {
+      0
+    }
+  }
+
+  fn path(&self) -> String {
+    let mut path = [0u16; PATH_LEN];
+    let mut len = PATH_LEN as u32;
+    let result = unsafe {
+      QueryFullProcessImageNameW(
+        self.0,
+        PROCESS_NAME_WIN32,
+        PWSTR(path.as_mut_ptr()),
+        &mut len,
+      )
+    };
+    if result.is_ok() {
+      String::from_utf16_lossy(&path[..len as usize])
+    } else {
+      String::new()
+    }
+  }
+
+  /// Package family name, empty unless this is a packaged (Store) app.
+  fn package(&self) -> String {
+    let mut package = [0u16; PACKAGE_LEN];
+    let mut len = PACKAGE_LEN as u32;
+    let result =
+      unsafe { GetPackageFamilyName(self.0, &mut len, Some(PWSTR(package.as_mut_ptr()))) };
+    if result == ERROR_SUCCESS {
+      // The length includes the terminating null
+      String::from_utf16_lossy(&package[..(len as usize).saturating_sub(1)])
+    } else {
+      String::new()
+    }
+  }
+
+  fn command_line(&self) -> String {
+    // The first call only asks how big the buffer has to be
+    let mut len = 0;
+    unsafe {
+      _ = NtQueryInformationProcess(
+        self.0,
+        ProcessCommandLineInformation,
+        ptr::null_mut(),
+        0,
+        &mut len,
+      );
+    }
+    if len == 0 {
+      return String::new();
+    }
+
+    // Kept in u64s so the UNICODE_STRING at the start is aligned
+    let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+    let status = unsafe {
+      NtQueryInformationProcess(
+        self.0,
+        ProcessCommandLineInformation,
+        buffer.as_mut_ptr() as _,
+        len,
+        &mut len,
+      )
+    };
+    if status.is_err() {
+      return String::new();
+    }
+
+    let text = unsafe { &*(buffer.as_ptr() as *const UNICODE_STRING) };
+    if text.Buffer.is_null() {
+      return String::new();
+    }
+    let units = unsafe { slice::from_raw_parts(text.Buffer.0, text.Length as usize / 2) };
+    String::from_utf16_lossy(units)
+  }
+}
+
+impl Drop for Process {
+  fn drop(&mut self) {
+    let _ = unsafe { CloseHandle(self.0) };
+  }
+}
+
+/// Processes we've already looked at, so coming back to a window or to
+/// another window of the same launcher doesn't query everything again.
+#[derive(Debug, Default)]
+pub struct ProcessCache {
+  processes: HashMap<u32, ProcessInfo>,
+}
+
+impl ProcessCache {
+  /// Looks up process `pid`, reading it again if its ID now belongs to a
+  /// different process. `None` for processes we can't open, such as elevated
+  /// ones.
+  pub fn get(&mut self, pid: u32) -> Option<&ProcessInfo> {
+    let process = Process::open(pid)?;
+    let created = process.created()?;
+
+    if self
+      .processes
+      .get(&pid)
+      .is_none_or(|info| info.created != created)
+    {
+      if self.processes.len() >= MAX_CACHED {
+        self.processes.clear();
+      }
+      let info = ProcessInfo {
+        created,
+        parent: process.parent(),
+        path: process.path(),
+        package: process.package(),
+        command_line: process.command_line(),
+      };
+      self.processes.insert(pid, info);
+    }
+
+    self.processes.get(&pid)
+  }
+
+  /// Fills in what window matchers need to know about process `pid` and the
+  /// processes that started it. Everything stays empty for processes we
+  /// can't open.
+  pub fn read_into(&mut self, pid: u32, window: &mut WindowInfo) {
+    window.path.clear();
+    window.package.clear();
+    window.command_line.clear();
+    window.ancestors.clear();
+
+    let Some(process) = self.get(pid) else {
+      return;
+    };
+    window.path.push_str(&process.path);
+    window.package.push_str(&process.package);
+    window.command_line.push_str(&process.command_line);
+
+    let (mut parent, mut child_created) = (process.parent, process.created);
+    while parent != 0 && window.ancestors.len() < MAX_ANCESTORS {
+      let Some(process) = self.get(parent) else {
+        break;
+      };
+      // A parent that started after its child is a newer process that got the
+      // ID after the real parent exited
+      if process.created > child_created {
+        break;
+      }
+      window.ancestors.push(process.path.clone());
+      (parent, child_created) = (process.parent, process.created);
+    }
+  }
+}
@@ -0,0 +1,839 @@
+use std::{
+  ops::Range,
+  sync::{
+    Arc,
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+  },
+  thread,
+};
+
+use once_cell::unsync::OnceCell;
+
+use crate::{
+  config::{
+    Detection, GameModeTiming, Key, KeyCombo, KeyConfig, KeyPress, KeyStroke, MAX_CHORD_STROKES,
+    WindowInfo, WindowMatcher,
+  },
+  detect::{self, Detector},
+  library::{self, GameLibrary},
+  modifiers::{ModifierSet, Modifiers},
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct RecordedPress {
+  key: KeyPress,
+  mods: Modifiers,
+  time: u32,
+}
+
+/// Recent key presses, used to match chord rules. Each recorded press came
+/// within the chord timeout of the one before it; a slower press starts a new
+/// sequence. Presses of modifier keys on their own are not recorded, so
+/// releasing and pressing Ctrl again between `ctrl+k` and `ctrl+c` is fine.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChordTracker {
+  presses: [RecordedPress; MAX_CHORD_STROKES],
+  len: usize,
+}
+
+impl ChordTracker {
+  pub const fn new() -> Self {
+    Self {
+      presses: [RecordedPress {
+        key: KeyPress {
+          vk: 0,
+          scan: 0,
+        },
+        mods: Modifiers::NONE,
+        time: 0,
+      }; MAX_CHORD_STROKES],
+      len: 0,
+    }
+  }
+
+  /// Records a new key press. `time` is in milliseconds and may wrap around,
+  /// like the timestamps of keyboard hook events.
+  pub fn push(&mut self, key: KeyPress, mods: Modifiers, time: u32, timeout_ms: u32) {
+    if Modifiers::from_vk(key.vk).is_some() {
+      return;
+    }
+
+    if self.len > 0 && time.wrapping_sub(self.presses[self.len - 1].time) > timeout_ms {
+      self.reset();
+    }
+
+    if self.len == MAX_CHORD_STROKES {
+      self.presses.copy_within(1.., 0);
+      self.len -= 1;
+    }
+
+    self.presses[self.len] = RecordedPress {
+      key,
+      mods,
+      time,
+    };
+    self.len += 1;
+  }
+
+  pub fn reset(&mut self) {
+    self.len = 0;
+  }
+
+  /// Returns true if the most recent presses match `strokes` in order.
+  fn ends_with(&self, strokes: &[CompiledStroke]) -> bool {
+    strokes.len() <= self.len
+      && strokes
+        .iter()
+        .zip(&self.presses[self.len - strokes.len()..self.len])
+        .all(|(stroke, press)| stroke.matches(press.key, press.mods))
+  }
+}
+
+/// A stroke with its modifier requirements expanded into every matching
+/// modifier state.
+#[derive(Debug, Clone, Copy)]
+struct CompiledStroke {
+  key: Option<Key>,
+  mods: ModifierSet,
+}
+
+impl CompiledStroke {
+  const EMPTY: Self = Self {
+    key: None,
+    mods: ModifierSet::EMPTY,
+  };
+
+  fn new(stroke: &KeyStroke) -> Self {
+    Self {
+      key: stroke.key,
+      mods: ModifierSet::from_fn(|mods| stroke.matches_modifiers(mods)),
+    }
+  }
+
+  fn matches(&self, key: KeyPress, mods: Modifiers) -> bool {
+    self.mods.contains(mods) && self.key.is_none_or(|k| k.matches(key))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleKind {
+  Block,
+  MaskTap,
+  Allow,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CompiledRule {
+  kind: RuleKind,
+  specificity: u8,
+  /// Strokes in the order they have to be pressed; only the first `len` are
+  /// used
+  strokes: [CompiledStroke; MAX_CHORD_STROKES],
+  len: usize,
+}
+
+impl CompiledRule {
+  fn new(kind: RuleKind, combo: &KeyCombo) -> Self {
+    let mut strokes = [CompiledStroke::EMPTY; MAX_CHORD_STROKES];
+    for (compiled, stroke) in strokes.iter_mut().zip(&combo.strokes) {
+      *compiled = CompiledStroke::new(stroke);
+    }
+
+    Self {
+      kind,
+      specificity: combo.specificity(),
+      strokes,
+      len: combo.strokes.len(),
+    }
+  }
+
+  fn last(&self) -> &CompiledStroke {
+    &self.strokes[self.len - 1]
+  }
+
+  /// Returns true if this key press completes the rule. Chords also need
+  /// their earlier strokes to be the most recent presses in `chords`, which
+  /// must already include this press.
+  fn matches(&self, key: KeyPress, mods: Modifiers, chords: &ChordTracker) -> bool {
+    self.last().matches(key, mods) && (self.len == 1 || chords.ends_with(&self.strokes[..self.len]))
+  }
+}
+
+/// Immutable form of one profile's lists that the keyboard hook can query
+/// without locking or allocating. Rules are grouped by the virtual-key code of
+/// their last stroke, so a key press only looks at the rules that can match it.
+#[derive(Debug)]
+pub struct CompiledRules {
+  /// Range of `rules` for each virtual-key code
+  by_vk: [(u16, u16); 256],
+  /// Rules that don't name a virtual-key code (scan codes and wildcards),
+  /// which are checked for every key
+  any_key: Range<usize>,
+  rules: Box<[CompiledRule]>,
+  pub detector: Box<dyn Detector>,
+}
+
+impl CompiledRules {
+  fn compile(
+    blacklist: &[KeyCombo],
+    whitelist: &[KeyCombo],
+    detection: &Detection,
+    library: &dyn Fn() -> Arc<GameLibrary>,
+  ) -> Self {
+    let all: Vec<CompiledRule> = blacklist
+      .iter()
+      .map(|combo| {
+        let kind = if combo.tap {
+          RuleKind::MaskTap
+        } else {
+          RuleKind::Block
+        };
+        CompiledRule::new(kind, combo)
+      })
+      .chain(
+        whitelist
+          .iter()
+          .map(|combo| CompiledRule::new(RuleKind::Allow, combo)),
+      )
+      .collect();
+
+    let vk_of = |rule: &CompiledRule| {
+      match rule.last().key {
+        Some(Key::Vk {
+          code, ..
+        }) if code < 256 => Some(code),
+        _ => None,
+      }
+    };
+
+    let mut rules = Vec::with_capacity(all.len());
+    let mut by_vk = [(0, 0); 256];
+    for (vk, range) in by_vk.iter_mut().enumerate() {
+      let start = rules.len();
+      rules.extend(all.iter().filter(|rule| vk_of(rule) == Some(vk as u16)));
+      *range = (start as u16, (rules.len() - start) as u16);
+    }
+    let start = rules.len();
+    rules.extend(all.iter().filter(|rule| vk_of(rule).is_none()));
+    let any_key = start..rules.len();
+
+    Self {
+      by_vk,
+      any_key,
+      rules: rules.into_boxed_slice(),
+      detector: detect::build(detection, library),
+    }
+  }
+
+  /// Specificity of the most specific rule of `kind` completed by this key
+  /// press, if any.
+  fn best_match(
+    &self,
+    kind: RuleKind,
+    key: KeyPress,
+    mods: Modifiers,
+    chords: &ChordTracker,
+  ) -> Option<u8> {
+    let (start, len) = self.by_vk.get(key.vk as usize).copied().unwrap_or_default();
+    let (start, len) = (start as usize, len as usize);
+
+    self.rules[start..start + len]
+      .iter()
+      .chain(&self.rules[self.any_key.clone()])
+      .filter(|rule| rule.kind == kind && rule.matches(key, mods, chords))
+      .map(|rule| rule.specificity)
+      .max()
+  }
+
+  /// Returns true if this key press should be blocked. `chords` holds the
+  /// recent presses, including this one, for matching chord rules.
+  pub fn should_block(&self, key: KeyPress, mods: Modifiers, chords: &ChordTracker) -> bool {
+    // Tap-only rules never block the key press itself
+    let blocked = self.best_match(RuleKind::Block, key, mods, chords);
+    let allowed = self.best_match(RuleKind::Allow, key, mods, chords);
+
+    // Block if the best blacklist rule is more specific than the best
+    // whitelist rule; on a tie the whitelist wins. `None` sorts below any match.
+    blocked > allowed
+  }
+
+  /// Returns true if a lone tap of this key should be masked on release.
+  pub fn should_suppress_tap(&self, key: KeyPress, mods: Modifiers, chords: &ChordTracker) -> bool {
+    let masked = self.best_match(RuleKind::MaskTap, key, mods, chords);
+    let allowed = self.best_match(RuleKind::Allow, key, mods, chords);
+
+    masked > allowed
+  }
+}
+
+/// Folds a path for comparison: letters are lowercased and forward slashes
+/// become backslashes.
+pub(crate) fn fold_path(path: &str) -> impl Iterator<Item = char> + '_ {
+  path
+    .chars()
+    .flat_map(char::to_lowercase)
+    .map(|c| if c == '/' { '\\' } else { c })
+}
+
+/// An executable a profile applies to, folded for comparison with process
+/// paths as reported by Windows.
+#[derive(Debug)]
+struct ExePattern {
+  folded: String,
+  /// Whether the pattern is a full path rather than just a file name
+  full_path: bool,
+}
+
+impl ExePattern {
+  fn new(pattern: &str) -> Self {
+    let folded: String = fold_path(pattern).collect();
+    let full_path = folded.contains('\\');
+    Self {
+      folded,
+      full_path,
+    }
+  }
+
+  fn matches(&self, window: &WindowInfo) -> bool {
+    let candidate = if self.full_path {
+      window.path.as_str()
+    } else {
+      window.exe()
+    };
+    fold_path(candidate).eq(self.folded.chars())
+  }
+}
+
+#[derive(Debug)]
+struct CompiledProfile {
+  executables: Box<[ExePattern]>,
+  window: Option<WindowMatcher>,
+  rules: CompiledRules,
+}
+
+/// Hands out a different generation to every compiled configuration.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Which profile applies to a window, as found by `profile_for`. It only
+/// stands for that profile in the configuration that found it: a reloaded
+/// one may list its profiles in another order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileMatch {
+  generation: u64,
+  index: Option<usize>,
+}
+
+/// Immutable form of a whole `KeyConfig`: the default rules, and the rules of
+/// each application profile.
+#[derive(Debug)]
+pub struct CompiledConfig {
+  generation: u64,
+  default: CompiledRules,
+  profiles: Box<[CompiledProfile]>,
+  force_game: Box<[WindowMatcher]>,
+  never_game: Box<[WindowMatcher]>,
+  pub game_mode: GameModeTiming,
+  pub chord_timeout_ms: u32,
+  /// How long key presses matter to detection, if any detector counts them
+  pub key_activity_window_ms: Option<u32>,
+}
+
+impl CompiledConfig {
+  pub fn compile(config: &KeyConfig) -> Self {
+    Self::compile_with_library(config, library::discover)
+  }
+
+  /// Compiles `config`, finding installed games with `discover`.
+  fn compile_with_library(config: &KeyConfig, discover: fn() -> GameLibrary) -> Self {
+    // Installed games are only looked up if a detector asks for them, and
+    // then only once for every profile
+    let discovered = OnceCell::new();
+    let library = || Arc::clone(discovered.get_or_init(|| Arc::new(discover())));
+
+    let profiles = config
+      .profiles
+      .iter()
+      .map(|profile| {
+        CompiledProfile {
+          executables: profile
+            .executables
+            .iter()
+            .map(|exe| ExePattern::new(exe))
+            .collect(),
+          window: profile.window.clone(),
+          rules: CompiledRules::compile(
+            &profile.blacklist,
+            &profile.whitelist,
+            profile
+              .detect_method
+              .as_ref()
+              .unwrap_or(&config.detect_method),
+            &library,
+          ),
+        }
+      })
+      .collect();
+
+    Self {
+      generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+      default: CompiledRules::compile(
+        &config.blacklist,
+        &config.whitelist,
+        &config.detect_method,
+        &library,
+      ),
+      profiles,
+      force_game: config.force_game.clone().into_boxed_slice(),
+      never_game: config.never_game.clone().into_boxed_slice(),
+      game_mode: config.game_mode,
+      chord_timeout_ms: config.chord_timeout_ms,
+      key_activity_window_ms: config
+        .profiles
+        .iter()
+        .filter_map(|profile| profile.detect_method.as_ref())
+        .chain([&config.detect_method])
+        .filter_map(Detection::key_activity_window_ms)
+        .max(),
+    }
+  }
+
+  /// The first profile that applies to `window`, if any.
+  pub fn profile_for(&self, window: &WindowInfo) -> ProfileMatch {
+    let index = self.profiles.iter().position(|profile| {
+      profile
+        .executables
+        .iter()
+        .any(|pattern| pattern.matches(window))
+        || profile
+          .window
+          .as_ref()
+          .is_some_and(|matcher| matcher.matches(window))
+    });
+    ProfileMatch {
+      generation: self.generation,
+      index,
+    }
+  }
+
+  /// Rules of the profile `profile_for` found, or the default rules if it
+  /// found none. Returns `None` if the profile was found in another
+  /// configuration, since we can't tell which of ours it stands for.
+  pub fn rules(&self, profile: ProfileMatch) -> Option<&CompiledRules> {
+    if profile.generation != self.generation {
+      return None;
+    }
+    Some(
+      profile
+        .index
+        .map_or(&self.default, |index| &self.profiles[index].rules),
+    )
+  }
+
+  /// Rules of the first profile that applies to `window`, or the default
+  /// rules if there is none.
+  #[cfg(test)]
+  pub fn rules_for(&self, window: &WindowInfo) -> &CompiledRules {
+    self.rules(self.profile_for(window)).unwrap()
+  }
+
+  /// Whether `window` is configured to always or never count as a game, in
+  /// which case the detect method isn't consulted.
+  pub fn game_override(&self, window: &WindowInfo) -> Option<bool> {
+    if self
+      .never_game
+      .iter()
+      .any(|matcher| matcher.matches(window))
+    {
+      Some(false)
+    } else if self
+      .force_game
+      .iter()
+      .any(|matcher| matcher.matches(window))
+    {
+      Some(true)
+    } else {
+      None
+    }
+  }
+}
+
+/// The configuration currently in use, replaced as a whole when it is
+/// reloaded.
+///
+/// Readers only bump a counter and load a pointer, so the keyboard hook never
+/// waits on a lock (Windows silently removes hooks that take too long). The
+/// cost moves to `publish`, which waits for readers of the old rules to
+/// finish before freeing them.
+#[derive(Debug)]
+pub struct ActiveConfig {
+  current: AtomicPtr<CompiledConfig>,
+  readers: AtomicUsize,
+}
+
+impl ActiveConfig {
+  pub fn new(config: CompiledConfig) -> Self {
+    Self {
+      current: AtomicPtr::new(Box::into_raw(Box::new(config))),
+      readers: AtomicUsize::new(0),
+    }
+  }
+
+  pub fn read<R>(&self, f: impl FnOnce(&CompiledConfig) -> R) -> R {
+    // Registering before loading the pointer means `publish` can't free the
+    // config we load until we're done with it
+    self.readers.fetch_add(1, Ordering::SeqCst);
+    let config = unsafe { &*self.current.load(Ordering::SeqCst) };
+    let result = f(config);
+    self.readers.fetch_sub(1, Ordering::SeqCst);
+    result
+  }
+
+  pub fn publish(&self, config: CompiledConfig) {
+    let old = self
+      .current
+      .swap(Box::into_raw(Box::new(config)), Ordering::SeqCst);
+
+    // Anyone who registers from now on sees the new config
+    while self.readers.load(Ordering::SeqCst) != 0 {
+      thread::yield_now();
+    }
+
+    drop(unsafe { Box::from_raw(old) });
+  }
+}
+
+impl Drop for ActiveConfig {
+  fn drop(&mut self) {
+    drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::{DetectMethod, EXTENDED_SCAN, Profile};
+
+  fn parse(list: &[&str]) -> Vec<KeyCombo> {
+    list
+      .iter()
+      .map(|s| KeyCombo::from_string(s).unwrap())
+      .collect()
+  }
+
+  fn compile(blacklist: &[&str], whitelist: &[&str]) -> CompiledRules {
+    CompiledRules::compile(
+      &parse(blacklist),
+      &parse(whitelist),
+      &Detection::default(),
+      &|| unreachable!(),
+    )
+  }
+
+  fn compile_config(blacklist: &[&str], profiles: Vec<Profile>) -> CompiledConfig {
+    CompiledConfig::compile(&KeyConfig {
+      blacklist: parse(blacklist),
+      profiles,
+      ..KeyConfig::default()
+    })
+  }
+
+  fn profile(executables: &[&str], blacklist: &[&str]) -> Profile {
+    Profile {
+      name: "test".to_string(),
+      executables: executables.iter().map(|exe| exe.to_string()).collect(),
+      window: None,
+      blacklist: parse(blacklist),
+      whitelist: vec![],
+      detect_method: None,
+    }
+  }
+
+  fn exe(path: &str) -> WindowInfo {
+    WindowInfo {
+      path: path.to_string(),
+      ..WindowInfo::new()
+    }
+  }
+
+  fn press(vk: u16) -> KeyPress {
+    KeyPress {
+      vk,
+      scan: 0,
+    }
+  }
+
+  const LWIN: u16 = 0x5B;
+  const D: u16 = 0x44;
+  const F1: u16 = 0x70;
+
+  #[test]
+  fn rules_are_found_through_their_key() {
+    let rules = compile(&["lwin", "shift+f1"], &[]);
+    let chords = ChordTracker::new();
+
+    assert!(rules.should_block(press(LWIN), Modifiers::LWIN, &chords));
+    assert!(rules.should_block(press(F1), Modifiers::RSHIFT, &chords));
+    assert!(!rules.should_block(press(F1), Modifiers::NONE, &chords));
+    assert!(!rules.should_block(press(D), Modifiers::LWIN, &chords));
+  }
+
+  #[test]
+  fn scan_codes_of_modifiers_hold_their_side() {
+    let rules = compile(&["sc:0x1D", "sc:e01d", "sc:e05b"], &[]);
+    let chords = ChordTracker::new();
+    let lctrl = KeyPress {
+      vk: 0xA2,
+      scan: 0x1D,
+    };
+    let rctrl = KeyPress {
+      vk: 0xA3,
+      scan: 0x1D | EXTENDED_SCAN,
+    };
+    let lwin = KeyPress {
+      vk: LWIN,
+      scan: 0x5B | EXTENDED_SCAN,
+    };
+
+    // The hook sees a modifier's own side as held when it goes down
+    assert!(rules.should_block(lctrl, Modifiers::LCTRL, &chords));
+    assert!(rules.should_block(rctrl, Modifiers::RCTRL, &chords));
+    assert!(rules.should_block(lwin, Modifiers::LWIN, &chords));
+    assert!(!rules.should_block(lctrl, Modifiers::LCTRL | Modifiers::LSHIFT, &chords));
+  }
+
+  #[test]
+  fn wildcards_apply_to_every_key() {
+    let rules = compile(&["win+*"], &["lwin+d"]);
+    let chords = ChordTracker::new();
+
+    assert!(rules.should_block(press(F1), Modifiers::LWIN, &chords));
+    assert!(!rules.should_block(press(D), Modifiers::LWIN, &chords));
+    assert!(!rules.should_block(press(F1), Modifiers::NONE, &chords));
+  }
+
+  #[test]
+  fn exact_rules_beat_wildcards_either_way() {
+    let rules = compile(&["lwin+d"], &["win+*"]);
+    let chords = ChordTracker::new();
+
+    assert!(rules.should_block(press(D), Modifiers::LWIN, &chords));
+    assert!(!rules.should_block(press(F1), Modifiers::LWIN, &chords));
+  }
+
+  #[test]
+  fn ties_go_to_the_whitelist() {
+    let chords = ChordTracker::new();
+
+    let rules = compile(&["lwin+d"], &["lwin+d"]);
+    assert!(!rules.should_block(press(D), Modifiers::LWIN, &chords));
+
+    let rules = compile(&["win+*"], &["win+*"]);
+    assert!(!rules.should_block(press(F1), Modifiers::LWIN, &chords));
+
+    // Different rules of the same kind tie too
+    let rules = compile(&["lwin+d"], &["win+d"]);
+    assert!(!rules.should_block(press(D), Modifiers::LWIN, &chords));
+  }
+
+  #[test]
+  fn chords_need_their_earlier_strokes() {
+    let rules = compile(&["ctrl+k ctrl+c"], &[]);
+    let mut chords = ChordTracker::new();
+
+    chords.push(press(0x43), Modifiers::LCTRL, 0, 1000);
+    assert!(!rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+
+    chords.push(press(0x4B), Modifiers::LCTRL, 100, 1000);
+    chords.push(press(0x43), Modifiers::LCTRL, 200, 1000);
+    assert!(rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+  }
+
+  #[test]
+  fn slow_strokes_start_a_new_chord() {
+    let rules = compile(&["ctrl+k ctrl+c"], &[]);
+    let mut chords = ChordTracker::new();
+
+    chords.push(press(0x4B), Modifiers::LCTRL, 0, 1000);
+    chords.push(press(0x43), Modifiers::LCTRL, 1001, 1000);
+    assert!(!rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+
+    // Tick counts wrap around
+    chords.push(press(0x4B), Modifiers::LCTRL, u32::MAX - 100, 1000);
+    chords.push(press(0x43), Modifiers::LCTRL, 100, 1000);
+    assert!(rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+  }
+
+  #[test]
+  fn other_keys_break_a_chord() {
+    let rules = compile(&["ctrl+k ctrl+c"], &[]);
+    let mut chords = ChordTracker::new();
+
+    chords.push(press(0x4B), Modifiers::LCTRL, 0, 1000);
+    chords.push(press(D), Modifiers::LCTRL, 100, 1000);
+    chords.push(press(0x43), Modifiers::LCTRL, 200, 1000);
+    assert!(!rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+
+    // Letting go of Ctrl and pressing it again doesn't
+    chords.push(press(0x4B), Modifiers::LCTRL, 300, 1000);
+    chords.push(press(0xA2), Modifiers::LCTRL, 400, 1000);
+    chords.push(press(0x43), Modifiers::LCTRL, 500, 1000);
+    assert!(rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+
+    chords.reset();
+    chords.push(press(0x43), Modifiers::LCTRL, 600, 1000);
+    assert!(!rules.should_block(press(0x43), Modifiers::LCTRL, &chords));
+  }
+
+  #[test]
+  fn tap_rules_only_mask() {
+    let rules = compile(&["tap:lwin"], &[]);
+    let chords = ChordTracker::new();
+
+    assert!(!rules.should_block(press(LWIN), Modifiers::LWIN, &chords));
+    assert!(rules.should_suppress_tap(press(LWIN), Modifiers::LWIN, &chords));
+  }
+
+  #[test]
+  fn profiles_are_picked_by_executable() {
+    let config = compile_config(
+      &["lwin"],
+      vec![
+        profile(&["Game.exe"], &["alt+tab"]),
+        profile(&["C:/Games/Other/launcher.exe"], &[]),
+      ],
+    );
+    let chords = ChordTracker::new();
+    let blocks_lwin = |path: &str| {
+      config
+        .rules_for(&exe(path))
+        .should_block(press(LWIN), Modifiers::LWIN, &chords)
+    };
+
+    assert!(!blocks_lwin(r"C:\Games\GAME.EXE"));
+    assert!(!blocks_lwin(r"c:\games\other\Launcher.exe"));
+    assert!(blocks_lwin(r"D:\Other\launcher.exe"));
+    assert!(blocks_lwin(r"C:\Games\mygame.exe"));
+    assert!(blocks_lwin(""));
+  }
+
+  #[test]
+  fn profiles_found_before_a_reload_are_not_used_after_it() {
+    let old = compile_config(
+      &[],
+      vec![profile(&["a.exe"], &["lwin"]), profile(&["b.exe"], &[])],
+    );
+    let new = compile_config(
+      &[],
+      vec![profile(&["b.exe"], &[]), profile(&["a.exe"], &["lwin"])],
+    );
+    let chords = ChordTracker::new();
+    let game = exe(r"C:\Games\a.exe");
+
+    let found = old.profile_for(&game);
+    assert!(
+      old
+        .rules(found)
+        .unwrap()
+        .should_block(press(LWIN), Modifiers::LWIN, &chords)
+    );
+    // The same index is another profile now
+    assert!(new.rules(found).is_none());
+    assert!(new.rules(new.profile_for(&game)).unwrap().should_block(
+      press(LWIN),
+      Modifiers::LWIN,
+      &chords
+    ));
+  }
+
+  #[test]
+  fn profiles_can_match_windows() {
+    let mut browser_game = profile(&[], &[]);
+    browser_game.window = Some(serde_json::from_str(r#"{"title": "glob:* - Game"}"#).unwrap());
+    let config = compile_config(&["lwin"], vec![browser_game]);
+    let chords = ChordTracker::new();
+    let blocks_lwin = |title: &str| {
+      let window = WindowInfo {
+        title: title.to_string(),
+        ..exe(r"C:\Browser\browser.exe")
+      };
+      config
+        .rules_for(&window)
+        .should_block(press(LWIN), Modifiers::LWIN, &chords)
+    };
+
+    assert!(!blocks_lwin("Puzzle - Game"));
+    assert!(blocks_lwin("Puzzle - News"));
+  }
+
+  #[test]
+  fn profiles_inherit_the_detect_method() {
+    let mut not_installed = profile(&["a.exe"], &[]);
+    not_installed.detect_method = Some(serde_json::from_str(r#"{"not": "game_library"}"#).unwrap());
+    let config = CompiledConfig::compile_with_library(
+      &KeyConfig {
+        detect_method: Detection::Method(DetectMethod::GameLibrary),
+        profiles: vec![not_installed, profile(&["b.exe"], &[])],
+        ..KeyConfig::default()
+      },
+      || {
+        let mut library = GameLibrary::default();
+        library.add(r"C:\Games");
+        library
+      },
+    );
+    let is_game = |path: &str| {
+      let window = exe(path);
+      config.rules_for(&window).detector.is_game(&window)
+    };
+
+    assert!(!is_game(r"C:\Games\a.exe"));
+    assert!(is_game(r"C:\Games\b.exe"));
+    assert!(!is_game(r"C:\Other\b.exe"));
+    assert!(is_game(r"C:\Games\c.exe"));
+  }
+
+  #[test]
+  fn never_game_beats_force_game() {
+    let config = CompiledConfig::compile(&KeyConfig {
+      force_game: serde_json::from_str(r#"[{"exe": "glob:*.exe"}]"#).unwrap(),
+      never_game: serde_json::from_str(r#"[{"exe": "vlc.exe"}]"#).unwrap(),
+      ..KeyConfig::default()
+    });
+
+    assert_eq!(config.game_override(&exe(r"C:\Games\game.exe")), Some(true));
+    assert_eq!(config.game_override(&exe(r"C:\VLC\vlc.exe")), Some(false));
+    assert_eq!(config.game_override(&exe(r"C:\Games\game.bin")), None);
+  }
+
+  #[test]
+  fn publish_swaps_rules_under_readers() {
+    let active = Arc::new(ActiveConfig::new(compile_config(&["lwin"], vec![])));
+    let reader = {
+      let active = Arc::clone(&active);
+      thread::spawn(move || {
+        for _ in 0..10_000 {
+          active.read(|config| {
+            config.rules_for(&WindowInfo::new()).should_block(
+              press(LWIN),
+              Modifiers::LWIN,
+              &ChordTracker::new(),
+            )
+          });
+        }
+      })
+    };
+
+    for i in 0..100 {
+      let blacklist: &[&str] = if i % 2 == 0 { &[] } else { &["lwin"] };
+      active.publish(compile_config(blacklist, vec![]));
+    }
+    reader.join().unwrap();
+
+    let chords = ChordTracker::new();
+    assert!(active.read(|config| {
+      config
+        .rules_for(&WindowInfo::new())
+        .should_block(press(LWIN), Modifiers::LWIN, &chords)
+    }));
+  }
+}